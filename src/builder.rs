@@ -1,13 +1,20 @@
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
+    cancel::CancellationToken,
+    compiled::CompiledSearch,
     config::CaseMode,
-    config::Config,
+    config::{Config, PatternSource},
     error::SearchError,
+    input::Preprocessor,
+    replace::Replacement,
+    report::SearchReport,
     search::Search,
     sink::MatchSink,
-    types::{ContextLine, Match},
+    types::{BinaryMode, ContextLine, FileResult, Match, Order, RewriteReport, SortKey, SubMatch},
 };
 
 /// Fluent builder for rg-style search configuration.
@@ -34,6 +41,30 @@ impl SearchBuilder {
         }
     }
 
+    /// Add another pattern to search for, like repeating `rg -e`.
+    ///
+    /// A line matches if any pattern matches. Each [`SubMatch`](crate::SubMatch)
+    /// records the index of the pattern that produced it, counting patterns in
+    /// the order they were added, starting with the one given to
+    /// [`new`](Self::new).
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.config
+            .patterns
+            .push(PatternSource::Pattern(pattern.into()));
+        self
+    }
+
+    /// Read patterns from a file, one per line, like `rg -f`.
+    ///
+    /// The file is read when the search runs; a missing or unreadable file is
+    /// reported as [`SearchError::Io`].
+    pub fn patterns_from_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.config
+            .patterns
+            .push(PatternSource::File(path.as_ref().to_path_buf()));
+        self
+    }
+
     pub fn path(mut self, path: impl AsRef<Path>) -> Self {
         self.config.paths = vec![path.as_ref().to_path_buf()];
         self
@@ -58,6 +89,45 @@ impl SearchBuilder {
         Search::from_config(self.config)
     }
 
+    /// Search asynchronously, returning a [`SearchStream`](crate::SearchStream)
+    /// of results. Requires the `tokio` feature and must be called from
+    /// within a Tokio runtime; otherwise it returns a [`SearchError::Io`] of
+    /// kind [`Unsupported`](std::io::ErrorKind::Unsupported).
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "tokio")]
+    /// # async fn run() -> Result<(), ripgrep_api::SearchError> {
+    /// use futures_core::Stream;
+    /// use ripgrep_api::SearchBuilder;
+    ///
+    /// let mut stream = Box::pin(SearchBuilder::new("alpha").path(".").search_stream()?);
+    /// while let Some(result) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+    ///     let mat = result?;
+    ///     println!("{}:{}", mat.path.display(), mat.line.unwrap_or(0));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn search_stream(self) -> Result<crate::SearchStream, SearchError> {
+        crate::SearchStream::from_config(self.config)
+    }
+
+    /// Build the matcher, globs and file types once and return a
+    /// [`CompiledSearch`] that can be run repeatedly against paths, readers
+    /// and slices.
+    ///
+    /// Pattern files are read here rather than on each run.
+    pub fn compile(self) -> Result<CompiledSearch, SearchError> {
+        CompiledSearch::new(self.config)
+    }
+
+    /// Search and return one [`FileResult`] per matching file, with matches
+    /// and context merged into hunks of contiguous lines.
+    pub fn file_results(self) -> Result<Vec<FileResult>, SearchError> {
+        crate::engine::file_results(&self.config)
+    }
+
     pub fn search_with<S: MatchSink>(self, sink: &mut S) -> Result<(), SearchError> {
         crate::engine::search_with(&self.config, sink)
     }
@@ -283,6 +353,92 @@ impl SearchBuilder {
         self
     }
 
+    /// Report each submatch as its own [`Match`], like `rg -o`.
+    ///
+    /// `bytes` and `line_text` then hold just the matched text, `line` and
+    /// `column` point at where it starts, and the single submatch covers the
+    /// whole of `bytes`. With a replacement, `replacement` holds the rewritten
+    /// submatch. Inverted searches produce no results in this mode.
+    pub fn only_matching(mut self) -> Self {
+        self.config.only_matching = true;
+        self
+    }
+
+    /// Allow matches to span multiple lines, like `rg -U`.
+    ///
+    /// Patterns may then contain `\n`, and a [`Match`] covers every line from
+    /// [`line`](Match::line) to [`end_line`](Match::end_line).
+    pub fn multiline(mut self) -> Self {
+        self.config.multiline = true;
+        self
+    }
+
+    /// Enable multiline mode and let `.` match line terminators too, like
+    /// `rg -U --multiline-dotall`.
+    pub fn multiline_dotall(mut self) -> Self {
+        self.config.multiline = true;
+        self.config.multiline_dotall = true;
+        self
+    }
+
+    /// Preview a replacement of every submatch, like `rg -r`.
+    ///
+    /// `$1`, `${1}`, `$name` and `${name}` in `template` refer to capture
+    /// groups and `$$` is a literal `$`. The rewritten line is stored in
+    /// [`Match::replacement`]; no files are modified.
+    ///
+    /// ```rust
+    /// use ripgrep_api::SearchBuilder;
+    ///
+    /// let matches = SearchBuilder::new(r"(?P<key>\w+)=(\d+)")
+    ///     .replace("$2=$key")
+    ///     .search_slice(b"answer=42\n")?;
+    ///
+    /// assert_eq!(matches[0].replacement.as_deref(), Some("42=answer\n"));
+    /// # Ok::<(), ripgrep_api::SearchError>(())
+    /// ```
+    pub fn replace(mut self, template: impl Into<String>) -> Self {
+        self.config.replacement = Some(Replacement::Template(template.into()));
+        self
+    }
+
+    /// Preview a replacement computed by `replace` for each submatch.
+    ///
+    /// Behaves like [`replace`](Self::replace), with the closure receiving the
+    /// submatch and its capture groups.
+    pub fn replace_with<F>(mut self, replace: F) -> Self
+    where
+        F: Fn(&SubMatch) -> String + Send + Sync + 'static,
+    {
+        self.config.replacement = Some(Replacement::With(Arc::new(replace)));
+        self
+    }
+
+    /// Make [`rewrite`](Self::rewrite) report unified diffs instead of
+    /// writing files.
+    pub fn dry_run(mut self) -> Self {
+        self.config.dry_run = true;
+        self
+    }
+
+    /// Keep a copy of each rewritten file at its path plus `suffix`, e.g.
+    /// `".bak"`.
+    pub fn backup(mut self, suffix: impl Into<String>) -> Self {
+        self.config.backup_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Report lines that do *not* match, like `rg -v`.
+    ///
+    /// Inverted results carry no submatches. `count` counts non-matching
+    /// lines, `files_with_matches` lists files containing at least one
+    /// non-matching line and `files_without_match` lists files where every
+    /// line matches.
+    pub fn invert_match(mut self) -> Self {
+        self.config.invert_match = true;
+        self
+    }
+
     pub fn before_context(mut self, lines: usize) -> Self {
         self.config.before_context = lines;
         self
@@ -293,12 +449,32 @@ impl SearchBuilder {
         self
     }
 
+    /// Deliver each match to streaming sinks with its context lines already
+    /// in [`Match::context`], as [`build`](Self::build) does.
+    ///
+    /// [`MatchSink::context`] is then not called. A match is delivered once
+    /// its after-context is complete.
+    pub fn attach_context(mut self) -> Self {
+        self.config.attach_context = true;
+        self
+    }
+
     pub fn context(mut self, lines: usize) -> Self {
         self.config.before_context = lines;
         self.config.after_context = lines;
         self
     }
 
+    /// Make [`count`](Self::count) and [`count_per_file`](Self::count_per_file)
+    /// count every match instead of matching lines, like `rg --count-matches`.
+    ///
+    /// `max_count` still limits the number of matching lines searched in each
+    /// file. Inverted searches keep counting lines.
+    pub fn count_matches(mut self) -> Self {
+        self.config.count_matches = true;
+        self
+    }
+
     pub fn max_count(mut self, count: usize) -> Self {
         self.config.max_count = Some(count);
         self
@@ -328,16 +504,205 @@ impl SearchBuilder {
         self
     }
 
+    /// Abort the search on the first unreadable file or directory instead
+    /// of skipping it.
+    pub fn strict(mut self) -> Self {
+        self.config.strict = true;
+        self
+    }
+
+    /// Record per-file errors and search statistics in `report` while the
+    /// search keeps going. See [`SearchReport::stats`].
+    pub fn report(mut self, report: &SearchReport) -> Self {
+        self.config.report = Some(report.clone());
+        self
+    }
+
+    /// Stop the search once `token` is cancelled.
+    ///
+    /// The token is checked between files and while each file is searched.
+    /// Results found before that are still delivered, and the search then
+    /// returns [`SearchError::Cancelled`]: [`build`](Self::build) ends the
+    /// iteration with it, and methods collecting their results such as
+    /// [`count`](Self::count) or [`walk_files`](Self::walk_files) return
+    /// what they had collected as its [`Partial`](crate::Partial). An
+    /// attached [`report`](Self::report) holds the statistics of the files
+    /// searched. An interrupted [`rewrite`](Self::rewrite) writes nothing.
+    pub fn cancel_token(mut self, token: &CancellationToken) -> Self {
+        self.config.cancel = Some(token.clone());
+        self
+    }
+
+    /// Stop the search once it has run for `timeout`, returning
+    /// [`SearchError::TimedOut`]. Partial results are handled as with
+    /// [`cancel_token`](Self::cancel_token).
+    ///
+    /// The clock starts when the search starts, so each run of a
+    /// [`CompiledSearch`] gets the full `timeout`.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use ripgrep_api::{Partial, SearchBuilder, SearchError};
+    ///
+    /// let result = SearchBuilder::new("alpha")
+    ///     .path(".")
+    ///     .deadline(Duration::from_secs(5))
+    ///     .count();
+    /// match result {
+    ///     Ok(count) => println!("{count} matching lines"),
+    ///     Err(SearchError::TimedOut {
+    ///         partial: Some(Partial::Count(count)),
+    ///     }) => println!("{count} matching lines so far"),
+    ///     Err(err) => return Err(err),
+    /// }
+    /// # Ok::<(), SearchError>(())
+    /// ```
+    pub fn deadline(mut self, timeout: Duration) -> Self {
+        self.config.deadline = Some(timeout);
+        self
+    }
+
+    /// Shorthand for [`binary`](Self::binary): `true` selects
+    /// [`BinaryMode::Skip`] and `false` selects [`BinaryMode::Text`].
     pub fn binary_detection(mut self, yes: bool) -> Self {
-        self.config.binary_detection = yes;
+        self.config.binary = if yes {
+            BinaryMode::Skip
+        } else {
+            BinaryMode::Text
+        };
+        self
+    }
+
+    /// Choose how files containing binary data are searched.
+    ///
+    /// Unless the mode is [`BinaryMode::Text`], [`MatchSink::binary`] is told
+    /// where binary data was found and an attached [`SearchReport`] records
+    /// the file.
+    pub fn binary(mut self, mode: BinaryMode) -> Self {
+        self.config.binary = mode;
+        self
+    }
+
+    /// Transcode input from the encoding named by `label` before searching,
+    /// like `rg -E`.
+    ///
+    /// Labels follow the [Encoding Standard], e.g. `"utf-16le"` or
+    /// `"windows-1252"`. A UTF-8 or UTF-16 byte order mark still takes
+    /// precedence. `"auto"` (the default) only transcodes files that start
+    /// with a BOM, and `"none"` searches raw bytes. Matches are found in the
+    /// transcoded UTF-8, so `line_text` is correct and byte offsets refer to
+    /// the transcoded text. An unknown label fails with
+    /// [`SearchError::InvalidEncoding`].
+    ///
+    /// [Encoding Standard]: https://encoding.spec.whatwg.org/#names-and-labels
+    pub fn encoding(mut self, label: impl Into<String>) -> Self {
+        self.config.encoding = Some(label.into());
+        self
+    }
+
+    /// Search compressed files, like `rg -z`.
+    ///
+    /// Files ending in `.gz`, `.bz2`, `.xz`, `.lzma`, `.zst`, `.lz4`, `.Z`
+    /// and similar extensions are decompressed by the matching external
+    /// tool (`gzip`, `bzip2`, `xz`, `zstd`, `lz4`, ...) when it is installed,
+    /// and searched as they stream in. Matches keep the compressed file's
+    /// path. Files whose tool is missing are searched as they are.
+    pub fn search_zip(mut self) -> Self {
+        self.config.search_zip = true;
+        self
+    }
+
+    /// Search the members of `.zip`, `.tar` and compressed tar archives
+    /// (`.tar.gz`, `.tgz`, `.tar.bz2`, `.tar.xz`, `.tar.zst`, ...).
+    ///
+    /// Members are reported under virtual paths like
+    /// `bundle.zip!/src/main.rs`. Globs and file types apply to the member
+    /// paths, so archives themselves are walked even when a glob or type
+    /// would exclude them, unless a negated glob or type names them.
+    /// Compressed tar archives need the same external tools as
+    /// [`search_zip`](Self::search_zip). Available with the `archives`
+    /// feature.
+    #[cfg(feature = "archives")]
+    pub fn search_archives(mut self) -> Self {
+        self.config.search_archives = true;
+        self
+    }
+
+    /// Search the output of `command` instead of each file, like `rg --pre`.
+    ///
+    /// The command is run with the file path as its only argument and the
+    /// file on stdin. Matches keep the original path. A preprocessor takes
+    /// precedence over [`search_zip`](Self::search_zip).
+    pub fn preprocessor(mut self, command: impl Into<PathBuf>) -> Self {
+        self.config.preprocessor = Some(Preprocessor::Command(command.into()));
+        self
+    }
+
+    /// Search the reader returned by `open` instead of each file.
+    ///
+    /// Behaves like [`preprocessor`](Self::preprocessor), e.g. for extracting
+    /// the text of documents in process.
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use ripgrep_api::SearchBuilder;
+    ///
+    /// let count = SearchBuilder::new("ALPHA")
+    ///     .path("tests/fixtures/root.txt")
+    ///     .preprocessor_with(|path| {
+    ///         let text = std::fs::read_to_string(path)?;
+    ///         Ok(Box::new(Cursor::new(text.to_uppercase())))
+    ///     })
+    ///     .count()?;
+    /// assert_eq!(count, 2);
+    /// # Ok::<(), ripgrep_api::SearchError>(())
+    /// ```
+    pub fn preprocessor_with<F>(mut self, open: F) -> Self
+    where
+        F: Fn(&Path) -> io::Result<Box<dyn Read>> + Send + Sync + 'static,
+    {
+        self.config.preprocessor = Some(Preprocessor::With(Arc::new(open)));
         self
     }
 
+    /// Only preprocess files matching `glob`, like `rg --pre-glob`. May be
+    /// given several times; without it every file is preprocessed.
+    pub fn pre_glob(mut self, glob: impl Into<String>) -> Self {
+        self.config.pre_globs.push(glob.into());
+        self
+    }
+
+    /// Search with `threads` worker threads, like `rg -j`.
+    ///
+    /// Any value other than `1` walks and searches files in parallel; `0`
+    /// picks a thread count automatically. Parallel results arrive in
+    /// whatever order the workers finish unless
+    /// [`deterministic`](Self::deterministic) is set.
     pub fn threads(mut self, threads: usize) -> Self {
         self.config.threads = Some(threads);
         self
     }
 
+    /// Visit files in the given order, like `rg --sort` and `rg --sortr`.
+    ///
+    /// Applies to every search, walk and count. As in ripgrep, sorting
+    /// turns off the parallel walk, and sorting by a timestamp collects the
+    /// whole walk before the first file is searched. Files whose timestamp
+    /// is unavailable are treated as older than every other file.
+    pub fn sort_by(mut self, key: SortKey, order: Order) -> Self {
+        self.config.sort = Some((key, order));
+        self
+    }
+
+    /// Deliver parallel results in path order so output is reproducible.
+    ///
+    /// Files are still searched on every thread, but results are held back
+    /// until the walk completes and then emitted sorted by path.
+    pub fn deterministic(mut self) -> Self {
+        self.config.deterministic = true;
+        self
+    }
+
     pub fn memory_map(mut self, choice: grep_searcher::MmapChoice) -> Self {
         self.config.memory_map = Some(choice);
         self
@@ -368,11 +733,52 @@ impl SearchBuilder {
         crate::engine::count(&self.config)
     }
 
+    /// Count per file, like `rg --count`. Files without a match are left
+    /// out.
+    pub fn count_per_file(self) -> Result<Vec<(PathBuf, u64)>, SearchError> {
+        crate::engine::count_per_file(&self.config)
+    }
+
     pub fn files_with_matches(self) -> Result<Vec<PathBuf>, SearchError> {
         crate::engine::files_with_matches(&self.config)
     }
 
+    /// List searched files with no match, like `rg --files-without-match`.
+    pub fn files_without_match(self) -> Result<Vec<PathBuf>, SearchError> {
+        crate::engine::files_without_match(&self.config)
+    }
+
     pub fn walk_files(self) -> Result<Vec<PathBuf>, SearchError> {
         crate::engine::walk_files(&self.config)
     }
+
+    /// Apply the configured [`replace`](Self::replace) or
+    /// [`replace_with`](Self::replace_with) to every matched file.
+    ///
+    /// Each file is written atomically through a temporary file that keeps
+    /// the original permissions. If any file fails, files already rewritten
+    /// are restored. A file or directory that cannot be read fails the
+    /// rewrite before anything is written, as in [`strict`](Self::strict)
+    /// mode. With [`dry_run`](Self::dry_run), nothing is written and
+    /// each [`FileRewrite`](crate::FileRewrite) carries a unified diff instead.
+    /// Files are edited as raw bytes; [`encoding`](Self::encoding) does not
+    /// apply.
+    ///
+    /// ```rust,no_run
+    /// use ripgrep_api::SearchBuilder;
+    ///
+    /// let report = SearchBuilder::new(r"old_name\(")
+    ///     .path("src")
+    ///     .replace("new_name(")
+    ///     .dry_run()
+    ///     .rewrite()?;
+    ///
+    /// for file in &report.files {
+    ///     print!("{}", file.diff.as_deref().unwrap_or_default());
+    /// }
+    /// # Ok::<(), ripgrep_api::SearchError>(())
+    /// ```
+    pub fn rewrite(self) -> Result<RewriteReport, SearchError> {
+        crate::rewrite::rewrite(&self.config)
+    }
 }
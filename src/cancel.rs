@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::error::SearchError;
+
+/// A handle for stopping a running search from another thread.
+///
+/// Clones share the same state, so a search configured with
+/// [`cancel_token`](crate::SearchBuilder::cancel_token) stops once any clone
+/// is cancelled. Results found before that are still delivered, and the
+/// search then returns [`SearchError::Cancelled`], carrying the
+/// [`Partial`](crate::Partial) results of methods that collect them.
+///
+/// ```rust
+/// use ripgrep_api::{CancellationToken, SearchBuilder, SearchError};
+///
+/// let token = CancellationToken::new();
+/// let result = SearchBuilder::new("alpha")
+///     .path(".")
+///     .cancel_token(&token)
+///     .for_each(|_| {
+///         token.cancel();
+///         true
+///     });
+/// assert!(matches!(result, Ok(()) | Err(SearchError::Cancelled { .. })));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop every search using this token. Cancelling cannot be undone.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+const RUNNING: u8 = 0;
+const CANCELLED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+
+/// The cancellation token and deadline of a single run, checked between
+/// files and by the matcher while a file is searched. Clones share the
+/// reason the run was stopped.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Interrupt {
+    token: Option<CancellationToken>,
+    deadline: Option<Instant>,
+    stopped: Arc<AtomicU8>,
+}
+
+impl Interrupt {
+    /// Start a run, counting the configured deadline from now.
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            token: config.cancel.clone(),
+            deadline: config.deadline.map(|timeout| Instant::now() + timeout),
+            stopped: Arc::default(),
+        }
+    }
+
+    /// Whether the run should stop, remembering why the first time it
+    /// should.
+    pub(crate) fn is_set(&self) -> bool {
+        if self.token.is_none() && self.deadline.is_none() {
+            return false;
+        }
+        if self.stopped.load(Ordering::Relaxed) != RUNNING {
+            return true;
+        }
+        let cancelled = self
+            .token
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled());
+        let reason = if cancelled {
+            CANCELLED
+        } else if self.deadline.is_some_and(|end| Instant::now() >= end) {
+            TIMED_OUT
+        } else {
+            return false;
+        };
+        self.stopped.store(reason, Ordering::Relaxed);
+        true
+    }
+
+    /// The error ending the run if it was stopped early.
+    pub(crate) fn result(&self) -> Result<(), SearchError> {
+        match self.stopped.load(Ordering::Relaxed) {
+            CANCELLED => Err(SearchError::Cancelled { partial: None }),
+            TIMED_OUT => Err(SearchError::TimedOut { partial: None }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ignore::overrides::Override;
+use ignore::types::Types;
+
+use crate::{
+    config::{Config, PatternSource},
+    engine,
+    error::SearchError,
+    input::Inputs,
+    matcher::{self, EngineMatcher},
+    search::Search,
+    sink::MatchSink,
+    types::{FileResult, Match},
+};
+
+/// The parts of a search that are expensive to build, kept in the [`Config`]
+/// of a [`CompiledSearch`] so every run reuses them.
+pub(crate) struct Compiled {
+    pub(crate) matcher: EngineMatcher,
+    pub(crate) overrides: Option<Override>,
+    pub(crate) types: Option<Types>,
+    pub(crate) inputs: Arc<Inputs>,
+}
+
+impl fmt::Debug for Compiled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Compiled").finish_non_exhaustive()
+    }
+}
+
+/// A search whose matcher, globs and file types are built once, returned by
+/// [`SearchBuilder::compile`](crate::SearchBuilder::compile).
+///
+/// Unlike the builder, every method borrows the search, so it can be run any
+/// number of times. It is cheap to clone and can be shared between threads.
+/// An attached [`SearchReport`](crate::SearchReport) accumulates the
+/// statistics of every run.
+///
+/// ```rust
+/// use ripgrep_api::SearchBuilder;
+///
+/// let search = SearchBuilder::new("alpha").glob("*.rs").compile()?;
+///
+/// let in_src = search.with_paths(["src"]).count()?;
+/// let in_tests = search.with_paths(["tests"]).count()?;
+/// let in_memory = search.search_slice(b"alpha\nbeta\n")?;
+/// # Ok::<(), ripgrep_api::SearchError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompiledSearch {
+    config: Config,
+}
+
+impl CompiledSearch {
+    pub(crate) fn new(mut config: Config) -> Result<Self, SearchError> {
+        let matcher = matcher::build_matcher(&config)?;
+        let (overrides, types) = engine::walker_filters(&config)?;
+        let inputs = Inputs::new(&config)?;
+        engine::searcher_builder(&config)?;
+        config.compiled = Some(Arc::new(Compiled {
+            matcher,
+            overrides,
+            types,
+            inputs,
+        }));
+        Ok(Self { config })
+    }
+
+    /// Return a copy searching for `pattern` instead, keeping every other
+    /// option. Only the matcher is rebuilt.
+    pub fn with_pattern(&self, pattern: impl Into<String>) -> Result<Self, SearchError> {
+        let mut config = self.config.clone();
+        config.patterns = vec![PatternSource::Pattern(pattern.into())];
+        let compiled = config.compiled.take().expect("compiled search");
+        let matcher = matcher::build_matcher(&config)?;
+        config.compiled = Some(Arc::new(Compiled {
+            matcher,
+            overrides: compiled.overrides.clone(),
+            types: compiled.types.clone(),
+            inputs: Arc::clone(&compiled.inputs),
+        }));
+        Ok(Self { config })
+    }
+
+    /// Return a copy searching `paths` instead of the configured paths. An
+    /// empty list keeps the configured paths.
+    pub fn with_paths<I, P>(&self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut config = self.config.clone();
+        let collected: Vec<PathBuf> = paths
+            .into_iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect();
+        if !collected.is_empty() {
+            config.paths = collected;
+        }
+        Self { config }
+    }
+
+    /// Search the configured paths lazily, like
+    /// [`SearchBuilder::build`](crate::SearchBuilder::build).
+    pub fn search(&self) -> Result<Search, SearchError> {
+        Search::from_config(self.config.clone())
+    }
+
+    /// Search the configured paths asynchronously, like
+    /// [`SearchBuilder::search_stream`](crate::SearchBuilder::search_stream).
+    #[cfg(feature = "tokio")]
+    pub fn search_stream(&self) -> Result<crate::SearchStream, SearchError> {
+        crate::SearchStream::from_config(self.config.clone())
+    }
+
+    pub fn search_with<S: MatchSink>(&self, sink: &mut S) -> Result<(), SearchError> {
+        engine::search_with(&self.config, sink)
+    }
+
+    pub fn file_results(&self) -> Result<Vec<FileResult>, SearchError> {
+        engine::file_results(&self.config)
+    }
+
+    pub fn search_reader<R: Read>(&self, reader: R) -> Result<Vec<Match>, SearchError> {
+        engine::search_reader(&self.config, reader, Path::new("<reader>"))
+    }
+
+    pub fn search_reader_with<R, S>(&self, reader: R, sink: &mut S) -> Result<(), SearchError>
+    where
+        R: Read,
+        S: MatchSink,
+    {
+        engine::search_reader_with(&self.config, reader, Path::new("<reader>"), sink)
+    }
+
+    pub fn search_slice(&self, slice: &[u8]) -> Result<Vec<Match>, SearchError> {
+        engine::search_slice(&self.config, slice, Path::new("<memory>"))
+    }
+
+    pub fn search_slice_with<S>(&self, slice: &[u8], sink: &mut S) -> Result<(), SearchError>
+    where
+        S: MatchSink,
+    {
+        engine::search_slice_with(&self.config, slice, Path::new("<memory>"), sink)
+    }
+
+    pub fn count(&self) -> Result<u64, SearchError> {
+        engine::count(&self.config)
+    }
+
+    pub fn count_per_file(&self) -> Result<Vec<(PathBuf, u64)>, SearchError> {
+        engine::count_per_file(&self.config)
+    }
+
+    pub fn files_with_matches(&self) -> Result<Vec<PathBuf>, SearchError> {
+        engine::files_with_matches(&self.config)
+    }
+
+    pub fn files_without_match(&self) -> Result<Vec<PathBuf>, SearchError> {
+        engine::files_without_match(&self.config)
+    }
+
+    pub fn walk_files(&self) -> Result<Vec<PathBuf>, SearchError> {
+        engine::walk_files(&self.config)
+    }
+}
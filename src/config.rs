@@ -1,10 +1,21 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use ignore::{overrides::Override, types::Types};
 
+use crate::{
+    cancel::CancellationToken,
+    compiled::Compiled,
+    input::Preprocessor,
+    replace::Replacement,
+    report::SearchReport,
+    types::{BinaryMode, Order, SortKey},
+};
+
 #[derive(Clone, Debug)]
 pub(crate) struct Config {
-    pub(crate) pattern: String,
+    pub(crate) patterns: Vec<PatternSource>,
     pub(crate) paths: Vec<PathBuf>,
     pub(crate) globs: Vec<String>,
     pub(crate) types: Vec<String>,
@@ -22,16 +33,44 @@ pub(crate) struct Config {
     pub(crate) before_context: usize,
     pub(crate) after_context: usize,
     pub(crate) max_count: Option<usize>,
+    pub(crate) attach_context: bool,
+    pub(crate) count_matches: bool,
     pub(crate) case_mode: CaseMode,
     pub(crate) fixed_strings: bool,
     pub(crate) word: bool,
     pub(crate) line_regexp: bool,
-    pub(crate) binary_detection: bool,
+    pub(crate) invert_match: bool,
+    pub(crate) only_matching: bool,
+    pub(crate) multiline: bool,
+    pub(crate) multiline_dotall: bool,
+    pub(crate) replacement: Option<Replacement>,
+    pub(crate) dry_run: bool,
+    pub(crate) backup_suffix: Option<String>,
+    pub(crate) binary: BinaryMode,
+    pub(crate) encoding: Option<String>,
+    pub(crate) search_zip: bool,
+    #[cfg(feature = "archives")]
+    pub(crate) search_archives: bool,
+    pub(crate) preprocessor: Option<Preprocessor>,
+    pub(crate) pre_globs: Vec<String>,
     pub(crate) engine: RegexEngine,
     pub(crate) threads: Option<usize>,
+    pub(crate) deterministic: bool,
+    pub(crate) sort: Option<(SortKey, Order)>,
     pub(crate) memory_map: Option<grep_searcher::MmapChoice>,
     pub(crate) heap_limit: Option<usize>,
     pub(crate) limit: Option<usize>,
+    pub(crate) strict: bool,
+    pub(crate) report: Option<SearchReport>,
+    pub(crate) cancel: Option<CancellationToken>,
+    pub(crate) deadline: Option<Duration>,
+    pub(crate) compiled: Option<Arc<Compiled>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PatternSource {
+    Pattern(String),
+    File(PathBuf),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -52,7 +91,7 @@ impl Config {
     pub(crate) fn new(pattern: String) -> Self {
         let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
         Self {
-            pattern,
+            patterns: vec![PatternSource::Pattern(pattern)],
             paths: vec![root],
             globs: Vec::new(),
             types: Vec::new(),
@@ -70,16 +109,38 @@ impl Config {
             before_context: 0,
             after_context: 0,
             max_count: None,
+            attach_context: false,
+            count_matches: false,
             case_mode: CaseMode::Smart,
             fixed_strings: false,
             word: false,
             line_regexp: false,
-            binary_detection: true,
+            invert_match: false,
+            only_matching: false,
+            multiline: false,
+            multiline_dotall: false,
+            replacement: None,
+            dry_run: false,
+            backup_suffix: None,
+            binary: BinaryMode::Skip,
+            encoding: None,
+            search_zip: false,
+            #[cfg(feature = "archives")]
+            search_archives: false,
+            preprocessor: None,
+            pre_globs: Vec::new(),
             engine: RegexEngine::Default,
             threads: None,
+            deterministic: false,
+            sort: None,
             memory_map: None,
             heap_limit: None,
             limit: None,
+            strict: false,
+            report: None,
+            cancel: None,
+            deadline: None,
+            compiled: None,
         }
     }
 }
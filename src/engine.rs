@@ -1,84 +1,255 @@
+use std::cmp::Reverse;
 use std::collections::BTreeSet;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
 
-use grep_matcher::Matcher;
-use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkMatch};
-use ignore::{overrides::OverrideBuilder, types::TypesBuilder, WalkBuilder};
+use grep_matcher::{Captures, Matcher};
+use grep_searcher::{BinaryDetection, Encoding, Searcher, SearcherBuilder, Sink, SinkMatch};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::{WalkBuilder, WalkState};
 
+use crate::cancel::Interrupt;
 use crate::config::Config;
-use crate::error::SearchError;
+use crate::error::{FileError, Partial, SearchError};
+use crate::input::{self, Inputs};
 use crate::matcher::{self, EngineMatcher};
+use crate::report::{FileStats, SearchReport};
 use crate::sink::MatchSink;
-use crate::types::{ContextKind, ContextLine, Match, SubMatch};
+use crate::types::{
+    BinaryMode, Capture, ContextKind, ContextLine, FileResult, Hunk, HunkLine, Match, Order,
+    SortKey, SubMatch,
+};
+
+/// Number of per-file results buffered between parallel workers and the
+/// consuming thread.
+const PARALLEL_BUFFER_SIZE: usize = 64;
+
+/// File type selecting the `extra` globs passed to [`build_types`]. Type
+/// names may only contain letters and digits.
+const EXTRA_TYPE: &str = "ripgrepapiextra";
+
+/// Internal receiver for the results of searching a file.
+///
+/// Returning `false` from `matched` or `context` stops the current file,
+/// while `quit` is checked between files to stop the whole walk.
+pub(crate) trait Emit {
+    fn matched(&mut self, mat: Match) -> bool;
+
+    fn context(&mut self, line: ContextLine) -> bool;
+
+    /// Called between non-contiguous groups of context within a file.
+    fn context_break(&mut self) -> bool {
+        true
+    }
 
-pub(crate) fn search(config: &Config) -> Result<Vec<Match>, SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
-    let mut results = Vec::new();
+    /// Called when binary data is found in the file at byte `offset`.
+    fn binary(&mut self, _path: &Path, _offset: u64) -> bool {
+        true
+    }
 
-    for entry in build_walker(config)?.build() {
-        let entry = entry?;
-        if !is_file_entry(&entry) {
-            continue;
-        }
+    fn finish(&mut self) {}
 
-        let remaining = remaining_limit(config.limit, results.len());
-        if remaining == Some(0) {
-            break;
-        }
+    fn error(&mut self, _error: &FileError) {}
 
-        let path = entry.path().to_path_buf();
-        let effective_max = effective_max_count(config.max_count, remaining);
-        let sink = CollectSink::new(&path, &matcher, &mut results, effective_max);
-        searcher.search_path(&matcher, &path, sink)?;
+    fn quit(&self) -> bool {
+        false
     }
+}
 
-    if let Some(limit) = config.limit {
-        results.truncate(limit);
-    }
+/// Receives the per-file outputs of [`walk_files_with`] on the calling
+/// thread. Closures taking the path and output implement it directly.
+pub(crate) trait Consume<T> {
+    /// Handle the output for `path`, returning `false` to stop the walk.
+    fn output(&mut self, path: PathBuf, output: T) -> bool;
 
-    Ok(results)
+    fn error(&mut self, _error: &FileError) {}
+}
+
+impl<T, F: FnMut(PathBuf, T) -> bool> Consume<T> for F {
+    fn output(&mut self, path: PathBuf, output: T) -> bool {
+        self(path, output)
+    }
 }
 
 pub(crate) fn search_with<S: MatchSink>(config: &Config, sink: &mut S) -> Result<(), SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
-    let mut global_count: usize = 0;
+    let matcher = matcher::build_matcher(config)?;
+    let walker = build_walker(config)?;
+    let inputs = Inputs::new(config)?;
+    let mut emit = Attach::around(SinkEmit(sink), config.attach_context);
+    search_walk(
+        config,
+        &matcher,
+        &walker,
+        &inputs,
+        &mut Limit::new(config.limit, &mut emit),
+    )
+}
+
+pub(crate) fn file_results(config: &Config) -> Result<Vec<FileResult>, SearchError> {
+    let matcher = matcher::build_matcher(config)?;
+    let walker = build_walker(config)?;
+    let inputs = Inputs::new(config)?;
+    let mut group = Group::default();
+    let result = search_walk(
+        config,
+        &matcher,
+        &walker,
+        &inputs,
+        &mut Limit::new(config.limit, &mut group),
+    );
+    SearchError::keep_partial(result, group.files, Partial::FileResults)
+}
+
+/// Walk every file selected by `walker` and feed its results into `emit`.
+pub(crate) fn search_walk<E: Emit>(
+    config: &Config,
+    matcher: &EngineMatcher,
+    walker: &WalkBuilder,
+    inputs: &Inputs,
+    emit: &mut E,
+) -> Result<(), SearchError> {
+    let interrupt = matcher.interrupt();
+    if is_parallel(config) {
+        let _timer = config.report.as_ref().map(SearchReport::timer);
+        let max_count = effective_max_count(config.max_count, config.limit);
+        let mut failed = None;
+        let result = walk_parallel(
+            config,
+            walker,
+            interrupt,
+            |searcher, path| {
+                let mut events = FileEvents::default();
+                let mut skipped = Vec::new();
+                let result = inputs.search_each(
+                    path,
+                    |path, input| {
+                        let sink = EventSink::new(config, path, matcher, &mut events, max_count);
+                        tracked(config, matcher, path, sink, |sink| {
+                            input.search(searcher, matcher, sink)
+                        })
+                    },
+                    defer(config, &mut skipped),
+                );
+                // Keep what the file produced before failing, as the
+                // sequential walk delivers it too.
+                events.0.extend(skipped.into_iter().map(Event::Error));
+                if let Err(err) = result {
+                    let error = FileError::new(path.to_path_buf(), err);
+                    events.0.extend([Event::Error(error), Event::Finish]);
+                }
+                Ok(events)
+            },
+            Replay {
+                config,
+                emit,
+                failed: &mut failed,
+            },
+        );
+        return failed.map_or(result, Err);
+    }
 
-    for entry in build_walker(config)?.build() {
-        let entry = entry?;
-        if !is_file_entry(&entry) {
+    let _timer = config.report.as_ref().map(SearchReport::timer);
+    let mut searcher = build_searcher(config)?;
+
+    for entry in sequential_entries(config, walker) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                recover(config, FileError::walk(err), |error| emit.error(error))?;
+                continue;
+            }
+        };
+        if !admit(config, &entry) {
             continue;
         }
-
-        let remaining = remaining_limit(config.limit, global_count);
-        if remaining == Some(0) {
+        if emit.quit() || interrupt.is_set() {
             break;
         }
 
-        let path = entry.path().to_path_buf();
-        let effective_max = effective_max_count(config.max_count, remaining);
-        let mut callback = CallbackSink::new(&path, &matcher, sink, effective_max);
-        searcher.search_path(&matcher, &path, &mut callback)?;
-        global_count = global_count.saturating_add(callback.match_count);
+        let mut skipped = Vec::new();
+        let result = inputs.search_each(
+            entry.path(),
+            |path, input| {
+                let sink = EventSink::new(config, path, matcher, emit, config.max_count);
+                tracked(config, matcher, path, sink, |sink| {
+                    input.search(&mut searcher, matcher, sink)
+                })
+            },
+            defer(config, &mut skipped),
+        );
+        for error in skipped {
+            notify_error(config, error, |error| emit.error(error));
+        }
+        if let Err(err) = result {
+            let error = FileError::new(entry.into_path(), err);
+            recover(config, error, |error| emit.error(error))?;
+            emit.finish();
+        }
     }
 
+    interrupt.result()
+}
+
+/// Handle a per-file error: fail fast in strict mode, otherwise pass it to
+/// `notify`, record it in the attached report and keep going.
+fn recover(
+    config: &Config,
+    error: FileError,
+    notify: impl FnOnce(&FileError),
+) -> Result<(), SearchError> {
+    if config.strict {
+        return Err(error.error);
+    }
+    notify_error(config, error, notify);
     Ok(())
 }
 
+/// Pass a skipped file's error to `notify` and record it in the attached
+/// report.
+fn notify_error(config: &Config, error: FileError, notify: impl FnOnce(&FileError)) {
+    notify(&error);
+    if let Some(report) = &config.report {
+        report.record_error(error);
+    }
+}
+
+/// Collect the errors of archive members skipped by
+/// [`Inputs::search_each`] into `skipped`, failing fast in strict mode.
+fn defer<'a>(
+    config: &'a Config,
+    skipped: &'a mut Vec<FileError>,
+) -> impl FnMut(FileError) -> Result<(), SearchError> + 'a {
+    move |error| {
+        if config.strict {
+            return Err(error.error);
+        }
+        skipped.push(error);
+        Ok(())
+    }
+}
+
 pub(crate) fn search_reader<R: io::Read>(
     config: &Config,
     reader: R,
     source: &Path,
 ) -> Result<Vec<Match>, SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
     let mut results = Vec::new();
-    let effective_max = effective_max_count(config.max_count, config.limit);
-    let sink = CollectSink::new(source, &matcher, &mut results, effective_max);
-    searcher.search_reader(&matcher, reader, sink)?;
-    Ok(results)
+    let mut collect = Attach::new(|mat| {
+        results.push(mat);
+        true
+    });
+    let result = search_reader_emit(
+        config,
+        reader,
+        source,
+        &mut Limit::new(config.limit, &mut collect),
+    );
+    drop(collect);
+    SearchError::keep_partial(result, results, Partial::Matches)
 }
 
 pub(crate) fn search_reader_with<R: io::Read, S: MatchSink>(
@@ -87,12 +258,29 @@ pub(crate) fn search_reader_with<R: io::Read, S: MatchSink>(
     source: &Path,
     sink: &mut S,
 ) -> Result<(), SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
-    let effective_max = effective_max_count(config.max_count, config.limit);
-    let mut callback = CallbackSink::new(source, &matcher, sink, effective_max);
-    searcher.search_reader(&matcher, reader, &mut callback)?;
-    Ok(())
+    let mut emit = Attach::around(SinkEmit(sink), config.attach_context);
+    search_reader_emit(
+        config,
+        reader,
+        source,
+        &mut Limit::new(config.limit, &mut emit),
+    )
+}
+
+fn search_reader_emit<R: io::Read, E: Emit>(
+    config: &Config,
+    reader: R,
+    source: &Path,
+    emit: &mut E,
+) -> Result<(), SearchError> {
+    let matcher = matcher::build_matcher(config)?;
+    let mut searcher = build_searcher(config)?;
+    let sink = EventSink::new(config, source, &matcher, emit, config.max_count);
+    let _timer = config.report.as_ref().map(SearchReport::timer);
+    tracked(config, &matcher, source, sink, |sink| {
+        searcher.search_reader(&matcher, reader, sink)
+    })?;
+    matcher.interrupt().result()
 }
 
 pub(crate) fn search_slice(
@@ -100,13 +288,19 @@ pub(crate) fn search_slice(
     slice: &[u8],
     source: &Path,
 ) -> Result<Vec<Match>, SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
     let mut results = Vec::new();
-    let effective_max = effective_max_count(config.max_count, config.limit);
-    let sink = CollectSink::new(source, &matcher, &mut results, effective_max);
-    searcher.search_slice(&matcher, slice, sink)?;
-    Ok(results)
+    let mut collect = Attach::new(|mat| {
+        results.push(mat);
+        true
+    });
+    let result = search_slice_emit(
+        config,
+        slice,
+        source,
+        &mut Limit::new(config.limit, &mut collect),
+    );
+    drop(collect);
+    SearchError::keep_partial(result, results, Partial::Matches)
 }
 
 pub(crate) fn search_slice_with<S: MatchSink>(
@@ -115,85 +309,356 @@ pub(crate) fn search_slice_with<S: MatchSink>(
     source: &Path,
     sink: &mut S,
 ) -> Result<(), SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
-    let effective_max = effective_max_count(config.max_count, config.limit);
-    let mut callback = CallbackSink::new(source, &matcher, sink, effective_max);
-    searcher.search_slice(&matcher, slice, &mut callback)?;
-    Ok(())
+    let mut emit = Attach::around(SinkEmit(sink), config.attach_context);
+    search_slice_emit(
+        config,
+        slice,
+        source,
+        &mut Limit::new(config.limit, &mut emit),
+    )
+}
+
+fn search_slice_emit<E: Emit>(
+    config: &Config,
+    slice: &[u8],
+    source: &Path,
+    emit: &mut E,
+) -> Result<(), SearchError> {
+    let matcher = matcher::build_matcher(config)?;
+    let mut searcher = build_searcher(config)?;
+    let sink = EventSink::new(config, source, &matcher, emit, config.max_count);
+    let _timer = config.report.as_ref().map(SearchReport::timer);
+    tracked(config, &matcher, source, sink, |sink| {
+        searcher.search_slice(&matcher, slice, sink)
+    })?;
+    matcher.interrupt().result()
 }
 
 pub(crate) fn count(config: &Config) -> Result<u64, SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
     let mut total = 0_u64;
+    let result = count_files(config, |_, count| total = total.saturating_add(count));
+    SearchError::keep_partial(result, total, Partial::Count)
+}
 
-    for entry in build_walker(config)?.build() {
-        let entry = entry?;
-        if !is_file_entry(&entry) {
-            continue;
-        }
+pub(crate) fn count_per_file(config: &Config) -> Result<Vec<(PathBuf, u64)>, SearchError> {
+    let mut counts = Vec::new();
+    let result = count_files(config, |path, count| counts.push((path, count)));
+    SearchError::keep_partial(result, counts, Partial::Counts)
+}
 
-        if let Some(limit) = config.limit {
-            if total >= limit as u64 {
-                break;
+/// Count lines or matches in every searched file, handing each non-zero
+/// count to `each`. The global limit truncates the last count and stops the
+/// walk.
+fn count_files<F>(config: &Config, mut each: F) -> Result<(), SearchError>
+where
+    F: FnMut(PathBuf, u64),
+{
+    let matcher = matcher::build_matcher(config)?;
+    let walker = build_walker(config)?;
+    let max_count = effective_max_count(config.max_count, config.limit);
+    let count_matches = config.count_matches && !config.invert_match;
+    let inputs = Inputs::new(config)?;
+    let mut total = 0_u64;
+
+    walk_files_with(
+        config,
+        &walker,
+        matcher.interrupt(),
+        |searcher, path| {
+            let mut counts = Vec::new();
+            inputs.search_each(
+                path,
+                |path, input| {
+                    let matches = count_matches.then_some(&matcher);
+                    let mut sink = CountSink::new(matches, max_count);
+                    tracked(config, &matcher, path, &mut sink, |sink| {
+                        input.search(searcher, &matcher, sink)
+                    })?;
+                    counts.push((path.to_path_buf(), sink.count()));
+                    Ok(())
+                },
+                |error| recover(config, error, |_| {}),
+            )?;
+            Ok(counts)
+        },
+        |_, counts: Vec<(PathBuf, u64)>| {
+            for (path, count) in counts {
+                let count = match config.limit {
+                    Some(limit) => count.min((limit as u64).saturating_sub(total)),
+                    None => count,
+                };
+                if count > 0 {
+                    total = total.saturating_add(count);
+                    each(path, count);
+                }
             }
-        }
+            config.limit.is_none_or(|limit| total < limit as u64)
+        },
+    )
+}
+
+pub(crate) fn files_with_matches(config: &Config) -> Result<Vec<PathBuf>, SearchError> {
+    files_by_match(config, true)
+}
+
+pub(crate) fn files_without_match(config: &Config) -> Result<Vec<PathBuf>, SearchError> {
+    files_by_match(config, false)
+}
 
-        let path = entry.path().to_path_buf();
-        let remaining = config.limit.map(|l| (l as u64).saturating_sub(total) as usize);
-        let effective_max = effective_max_count(config.max_count, remaining);
-        let mut sink = CountSink::new(effective_max);
-        searcher.search_path(&matcher, &path, &mut sink)?;
-        total = total.saturating_add(sink.count());
+/// Collect the searched files whose "has a match" outcome equals `wanted`.
+fn files_by_match(config: &Config, wanted: bool) -> Result<Vec<PathBuf>, SearchError> {
+    let matcher = matcher::build_matcher(config)?;
+    let walker = build_walker(config)?;
+    let inputs = Inputs::new(config)?;
+    let mut seen = BTreeSet::new();
+    let mut files = Vec::new();
+
+    let result = walk_files_with(
+        config,
+        &walker,
+        matcher.interrupt(),
+        |searcher, path| {
+            let mut outcomes = Vec::new();
+            inputs.search_each(
+                path,
+                |path, input| {
+                    let mut sink = FirstMatchSink::new();
+                    tracked(config, &matcher, path, &mut sink, |sink| {
+                        input.search(searcher, &matcher, sink)
+                    })?;
+                    outcomes.push((path.to_path_buf(), sink.found()));
+                    Ok(())
+                },
+                |error| recover(config, error, |_| {}),
+            )?;
+            Ok(outcomes)
+        },
+        |_, outcomes: Vec<(PathBuf, bool)>| {
+            for (path, found) in outcomes {
+                if found == wanted && seen.insert(path.clone()) {
+                    files.push(path);
+                }
+            }
+            true
+        },
+    );
+
+    if config.sort.is_none() {
+        files.sort();
     }
+    SearchError::keep_partial(result, files, Partial::Files)
+}
+
+pub(crate) fn walk_files(config: &Config) -> Result<Vec<PathBuf>, SearchError> {
+    let walker = build_walker(config)?;
+    let inputs = Inputs::new(config)?;
+    let mut files = Vec::new();
+
+    let result = walk_files_with(
+        config,
+        &walker,
+        &Interrupt::new(config),
+        |_, path| {
+            let mut paths = Vec::new();
+            inputs.search_each(
+                path,
+                |path, _| {
+                    paths.push(path.to_path_buf());
+                    Ok(())
+                },
+                |error| recover(config, error, |_| {}),
+            )?;
+            Ok(paths)
+        },
+        |_, paths: Vec<PathBuf>| {
+            files.extend(paths);
+            true
+        },
+    );
+
+    SearchError::keep_partial(result, files, Partial::Files)
+}
 
-    if let Some(limit) = config.limit {
-        total = total.min(limit as u64);
+/// Whether the configured thread count asks for a parallel walk.
+fn is_parallel(config: &Config) -> bool {
+    config.threads.is_some_and(|threads| threads != 1) && config.sort.is_none()
+}
+
+type Entries = Box<dyn Iterator<Item = Result<ignore::DirEntry, ignore::Error>>>;
+
+/// Walk sequentially in the order configured by `sort_by`. Path order is
+/// applied by the walker itself; sorting by a timestamp has to collect the
+/// whole walk first.
+fn sequential_entries(config: &Config, walker: &WalkBuilder) -> Entries {
+    let Some((key, order)) = config.sort else {
+        return Box::new(walker.build());
+    };
+    if key == SortKey::Path {
+        return Box::new(walker.build());
     }
 
-    Ok(total)
+    let time = |entry: &Result<ignore::DirEntry, ignore::Error>| {
+        let metadata = entry.as_ref().ok()?.metadata().ok()?;
+        match key {
+            SortKey::Modified => metadata.modified(),
+            SortKey::Accessed => metadata.accessed(),
+            SortKey::Created => metadata.created(),
+            SortKey::Path => unreachable!("path order is applied by the walker"),
+        }
+        .ok()
+    };
+    let mut entries: Vec<_> = walker.build().collect();
+    match order {
+        Order::Ascending => entries.sort_by_cached_key(time),
+        Order::Descending => entries.sort_by_cached_key(|entry| Reverse(time(entry))),
+    }
+    Box::new(entries.into_iter())
 }
 
-pub(crate) fn files_with_matches(config: &Config) -> Result<Vec<PathBuf>, SearchError> {
-    let matcher = matcher::build_matcher(&config.pattern, config)?;
-    let mut searcher = build_searcher(config);
-    let mut files = BTreeSet::new();
+/// Run `work` for every file selected by `walker` and hand each result to
+/// `consume` on the calling thread, in parallel when the config asks for it.
+/// The walk stops early once `interrupt` is set.
+pub(crate) fn walk_files_with<T, W, C>(
+    config: &Config,
+    walker: &WalkBuilder,
+    interrupt: &Interrupt,
+    work: W,
+    mut consume: C,
+) -> Result<(), SearchError>
+where
+    T: Send,
+    W: Fn(&mut Searcher, &Path) -> Result<T, SearchError> + Sync,
+    C: Consume<T>,
+{
+    let _timer = config.report.as_ref().map(SearchReport::timer);
+    if is_parallel(config) {
+        return walk_parallel(config, walker, interrupt, work, consume);
+    }
 
-    for entry in build_walker(config)?.build() {
-        let entry = entry?;
-        if !is_file_entry(&entry) {
+    let mut searcher = build_searcher(config)?;
+    for entry in sequential_entries(config, walker) {
+        if interrupt.is_set() {
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                recover(config, FileError::walk(err), |error| consume.error(error))?;
+                continue;
+            }
+        };
+        if !admit(config, &entry) {
             continue;
         }
 
-        let path = entry.path().to_path_buf();
-        let mut sink = FirstMatchSink::new();
-        searcher.search_path(&matcher, &path, &mut sink)?;
-        if sink.found() {
-            files.insert(path);
+        match work(&mut searcher, entry.path()) {
+            Ok(output) => {
+                if !consume.output(entry.into_path(), output) {
+                    break;
+                }
+            }
+            Err(err) => {
+                let error = FileError::new(entry.into_path(), err);
+                recover(config, error, |error| consume.error(error))?;
+            }
         }
     }
 
-    Ok(files.into_iter().collect())
+    interrupt.result()
 }
 
-pub(crate) fn walk_files(config: &Config) -> Result<Vec<PathBuf>, SearchError> {
-    let mut files = Vec::new();
+/// Parallel counterpart of [`walk_files_with`]. Every walker thread owns its
+/// own [`Searcher`]; results are funneled back over a bounded channel. With
+/// `deterministic` set, results are buffered and replayed in path order once
+/// the walk completes.
+fn walk_parallel<T, W, C>(
+    config: &Config,
+    walker: &WalkBuilder,
+    interrupt: &Interrupt,
+    work: W,
+    mut consume: C,
+) -> Result<(), SearchError>
+where
+    T: Send,
+    W: Fn(&mut Searcher, &Path) -> Result<T, SearchError> + Sync,
+    C: Consume<T>,
+{
+    let quit = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::sync_channel(PARALLEL_BUFFER_SIZE);
+    let mut ordered = Vec::new();
+
+    let searcher = searcher_builder(config)?;
+    let result = thread::scope(|scope| {
+        let walk = walker.build_parallel();
+        let searcher = &searcher;
+        let work = &work;
+        let quit = &quit;
+        scope.spawn(move || {
+            walk.run(|| {
+                let sender = sender.clone();
+                let mut searcher = searcher.build();
+                Box::new(move |entry| {
+                    if quit.load(Ordering::Relaxed) || interrupt.is_set() {
+                        return WalkState::Quit;
+                    }
+                    let output = match entry {
+                        Ok(entry) if !admit(config, &entry) => return WalkState::Continue,
+                        Ok(entry) => match work(&mut searcher, entry.path()) {
+                            Ok(output) => Ok((entry.into_path(), output)),
+                            Err(err) => Err(FileError::new(entry.into_path(), err)),
+                        },
+                        Err(err) => Err(FileError::walk(err)),
+                    };
+                    match sender.send(output) {
+                        Ok(()) => WalkState::Continue,
+                        Err(_) => WalkState::Quit,
+                    }
+                })
+            });
+        });
 
-    for entry in build_walker(config)?.build() {
-        let entry = entry?;
-        if !is_file_entry(&entry) {
-            continue;
+        let mut result = Ok(());
+        while let Ok(output) = receiver.recv() {
+            match output {
+                Ok((path, output)) if config.deterministic => ordered.push((path, output)),
+                Ok((path, output)) => {
+                    if !consume.output(path, output) {
+                        break;
+                    }
+                }
+                Err(error) => {
+                    if let Err(err) = recover(config, error, |error| consume.error(error)) {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+        }
+        quit.store(true, Ordering::Relaxed);
+        drop(receiver);
+        result
+    });
+    result?;
+
+    ordered.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, output) in ordered {
+        if !consume.output(path, output) {
+            break;
         }
-        files.push(entry.path().to_path_buf());
     }
 
-    Ok(files)
+    interrupt.result()
+}
+
+fn build_searcher(config: &Config) -> Result<Searcher, SearchError> {
+    Ok(searcher_builder(config)?.build())
 }
 
-fn build_searcher(config: &Config) -> Searcher {
+pub(crate) fn searcher_builder(config: &Config) -> Result<SearcherBuilder, SearchError> {
     let mut builder = SearcherBuilder::new();
     builder.line_number(true);
+    builder.invert_match(config.invert_match);
+    builder.multi_line(config.multiline);
     builder.before_context(config.before_context);
     builder.after_context(config.after_context);
     if let Some(choice) = config.memory_map.clone() {
@@ -202,15 +667,26 @@ fn build_searcher(config: &Config) -> Searcher {
     if let Some(limit) = config.heap_limit {
         builder.heap_limit(Some(limit));
     }
-    if config.binary_detection {
-        builder.binary_detection(BinaryDetection::quit(b'\x00'));
-    } else {
-        builder.binary_detection(BinaryDetection::none());
+    builder.binary_detection(match config.binary {
+        BinaryMode::Skip => BinaryDetection::quit(b'\x00'),
+        BinaryMode::Convert => BinaryDetection::convert(b'\x00'),
+        BinaryMode::Text => BinaryDetection::none(),
+    });
+    match config.encoding.as_deref() {
+        None | Some("auto") => {}
+        Some("none") => {
+            builder.bom_sniffing(false);
+        }
+        Some(label) => {
+            let encoding = Encoding::new(label)
+                .map_err(|_| SearchError::InvalidEncoding(label.to_string()))?;
+            builder.encoding(Some(encoding));
+        }
     }
-    builder.build()
+    Ok(builder)
 }
 
-fn build_walker(config: &Config) -> Result<WalkBuilder, SearchError> {
+pub(crate) fn build_walker(config: &Config) -> Result<WalkBuilder, SearchError> {
     let mut builder = WalkBuilder::new(&config.paths[0]);
     for path in config.paths.iter().skip(1) {
         builder.add(path);
@@ -218,7 +694,6 @@ fn build_walker(config: &Config) -> Result<WalkBuilder, SearchError> {
 
     builder
         .max_depth(config.max_depth)
-        .max_filesize(config.max_filesize)
         .follow_links(config.follow_links)
         .hidden(!config.search_hidden)
         .parents(config.ignore_parent)
@@ -231,61 +706,113 @@ fn build_walker(config: &Config) -> Result<WalkBuilder, SearchError> {
         builder.threads(threads);
     }
 
+    if let Some((SortKey::Path, order)) = config.sort {
+        builder.sort_by_file_path(move |a, b| match order {
+            Order::Ascending => a.cmp(b),
+            Order::Descending => b.cmp(a),
+        });
+    }
+
+    let (overrides, types) = match &config.compiled {
+        Some(compiled) => (compiled.overrides.clone(), compiled.types.clone()),
+        None => walker_filters(config)?,
+    };
+    if let Some(overrides) = overrides {
+        builder.overrides(overrides);
+    }
+    if let Some(types) = types {
+        builder.types(types);
+    }
+
+    Ok(builder)
+}
+
+/// Build the glob overrides and file types applied by the walker.
+pub(crate) fn walker_filters(
+    config: &Config,
+) -> Result<(Option<Override>, Option<Types>), SearchError> {
+    // Archives must reach the archive reader even when the filters select
+    // other files; the filters are applied to their members instead.
+    let extra = input::archive_globs(config);
+    Ok((build_overrides(config, extra)?, build_types(config, extra)?))
+}
+
+/// Build the glob overrides, whitelisting `extra` globs too when the
+/// configured globs whitelist anything.
+pub(crate) fn build_overrides(
+    config: &Config,
+    extra: &[&str],
+) -> Result<Option<Override>, SearchError> {
     if let Some(overrides) = &config.overrides {
-        builder.overrides(overrides.clone());
-    } else if !config.globs.is_empty() {
-        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let mut overrides = OverrideBuilder::new(cwd);
-        for glob in &config.globs {
+        return Ok(Some(overrides.clone()));
+    }
+    if config.globs.is_empty() {
+        return Ok(None);
+    }
+
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let mut overrides = OverrideBuilder::new(cwd);
+    for glob in &config.globs {
+        overrides
+            .add(glob)
+            .map_err(|err| SearchError::InvalidGlob(err.to_string()))?;
+    }
+    if config.globs.iter().any(|glob| !glob.starts_with('!')) {
+        for glob in extra {
             overrides
                 .add(glob)
                 .map_err(|err| SearchError::InvalidGlob(err.to_string()))?;
         }
-        builder.overrides(
-            overrides
-                .build()
-                .map_err(|err| SearchError::InvalidGlob(err.to_string()))?,
-        );
     }
+    overrides
+        .build()
+        .map(Some)
+        .map_err(|err| SearchError::InvalidGlob(err.to_string()))
+}
 
+/// Build the file type matcher, also selecting files matching `extra` when
+/// any type is selected.
+pub(crate) fn build_types(config: &Config, extra: &[&str]) -> Result<Option<Types>, SearchError> {
     if let Some(types) = &config.types_override {
-        builder.types(types.clone());
-    } else if !config.types.is_empty()
-        || !config.type_not.is_empty()
-        || !config.type_defs.is_empty()
-    {
-        let mut types = TypesBuilder::new();
-        types.add_defaults();
-        for (name, glob) in &config.type_defs {
+        return Ok(Some(types.clone()));
+    }
+    if config.types.is_empty() && config.type_not.is_empty() && config.type_defs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut types = TypesBuilder::new();
+    types.add_defaults();
+    for (name, glob) in &config.type_defs {
+        types
+            .add(name, glob)
+            .map_err(|err| SearchError::InvalidType(err.to_string()))?;
+    }
+    for name in &config.types {
+        types.select(name);
+    }
+    if !config.types.is_empty() && !extra.is_empty() {
+        for glob in extra {
             types
-                .add(name, glob)
+                .add(EXTRA_TYPE, glob)
                 .map_err(|err| SearchError::InvalidType(err.to_string()))?;
         }
-        for name in &config.types {
-            types.select(name);
-        }
-        for name in &config.type_not {
-            types.negate(name);
-        }
-        builder.types(
-            types
-                .build()
-                .map_err(|err| SearchError::InvalidType(err.to_string()))?,
-        );
+        types.select(EXTRA_TYPE);
     }
-
-    Ok(builder)
-}
-
-/// Given a global limit and the current count, return how many results remain.
-/// `None` means unlimited.
-fn remaining_limit(limit: Option<usize>, current: usize) -> Option<usize> {
-    limit.map(|l| l.saturating_sub(current))
+    for name in &config.type_not {
+        types.negate(name);
+    }
+    types
+        .build()
+        .map(Some)
+        .map_err(|err| SearchError::InvalidType(err.to_string()))
 }
 
 /// Combine `max_count` (per-file) with an optional remaining global limit,
 /// returning the tighter of the two.
-fn effective_max_count(max_count: Option<usize>, remaining: Option<usize>) -> Option<usize> {
+pub(crate) fn effective_max_count(
+    max_count: Option<usize>,
+    remaining: Option<usize>,
+) -> Option<usize> {
     match (max_count, remaining) {
         (Some(mc), Some(rem)) => Some(mc.min(rem)),
         (Some(mc), None) => Some(mc),
@@ -294,6 +821,92 @@ fn effective_max_count(max_count: Option<usize>, remaining: Option<usize>) -> Op
     }
 }
 
+/// Find every submatch in `bytes`, which start at absolute byte `offset` in
+/// the searched input, resolving capture groups when the pattern has any.
+pub(crate) fn find_submatches(
+    matcher: &DynMatcher,
+    bytes: &[u8],
+    offset: u64,
+) -> io::Result<Vec<SubMatch>> {
+    let mut submatches = Vec::new();
+    if matcher.capture_count() <= 1 {
+        matcher
+            .find_iter(bytes, |m| {
+                submatches.push(SubMatch {
+                    start: m.start(),
+                    end: m.end(),
+                    byte_offset: offset + m.start() as u64,
+                    pattern: matcher.pattern_index(bytes, m),
+                    captures: Vec::new(),
+                });
+                true
+            })
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        return Ok(submatches);
+    }
+
+    let mut caps = matcher
+        .new_captures()
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    matcher
+        .captures_iter(bytes, &mut caps, |caps| {
+            let Some(m) = caps.get(0) else {
+                return true;
+            };
+            let captures = (0..caps.len())
+                .map(|index| {
+                    caps.get(index).map(|group| Capture {
+                        index,
+                        name: matcher.group_name(index).map(str::to_string),
+                        start: group.start(),
+                        end: group.end(),
+                        text: String::from_utf8_lossy(&bytes[group]).to_string(),
+                    })
+                })
+                .collect();
+            submatches.push(SubMatch {
+                start: m.start(),
+                end: m.end(),
+                byte_offset: offset + m.start() as u64,
+                pattern: matcher.pattern_index(bytes, m),
+                captures,
+            });
+            true
+        })
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok(submatches)
+}
+
+/// Count the line terminators inside `bytes`, ignoring a trailing one, to
+/// find how many lines past the first a match covers.
+fn count_line_breaks(bytes: &[u8]) -> u64 {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    bytes.iter().filter(|&&byte| byte == b'\n').count() as u64
+}
+
+/// Decide whether a walk entry should be searched, recording it in the
+/// attached report. `max_filesize` is enforced here rather than by the
+/// walker so skipped files can be counted.
+fn admit(config: &Config, entry: &ignore::DirEntry) -> bool {
+    if !is_file_entry(entry) {
+        return false;
+    }
+    if let Some(report) = &config.report {
+        report.record_file_walked();
+    }
+
+    let Some(max_filesize) = config.max_filesize else {
+        return true;
+    };
+    let too_large = entry
+        .metadata()
+        .is_ok_and(|metadata| metadata.len() > max_filesize);
+    if too_large && let Some(report) = &config.report {
+        report.record_skipped_filesize();
+    }
+    !too_large
+}
+
 fn is_file_entry(entry: &ignore::DirEntry) -> bool {
     entry
         .file_type()
@@ -303,74 +916,255 @@ fn is_file_entry(entry: &ignore::DirEntry) -> bool {
 
 type DynMatcher = EngineMatcher;
 
-struct CollectSink<'a> {
+/// Run a single search through `run` with `sink` wrapped in a [`StatsSink`],
+/// recording the file's statistics in the attached report.
+pub(crate) fn tracked<S, R>(
+    config: &Config,
+    matcher: &DynMatcher,
+    path: &Path,
+    sink: S,
+    run: R,
+) -> Result<(), SearchError>
+where
+    S: Sink<Error = io::Error>,
+    R: FnOnce(&mut StatsSink<'_, S>) -> Result<(), io::Error>,
+{
+    let mut sink = StatsSink {
+        inner: sink,
+        matcher,
+        enabled: config.report.is_some(),
+        invert_match: config.invert_match,
+        stats: FileStats {
+            binary_skipped: config.binary == BinaryMode::Skip,
+            ..FileStats::default()
+        },
+    };
+    run(&mut sink)?;
+    if let Some(report) = &config.report {
+        report.record_file(path, &sink.stats);
+    }
+    Ok(())
+}
+
+/// Forwards to another sink while counting matched lines, matches and bytes.
+pub(crate) struct StatsSink<'a, S> {
+    inner: S,
+    matcher: &'a DynMatcher,
+    enabled: bool,
+    invert_match: bool,
+    stats: FileStats,
+}
+
+impl<S: Sink<Error = io::Error>> Sink for StatsSink<'_, S> {
+    type Error = io::Error;
+
+    fn matched(&mut self, searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        // An interrupted matcher finds nothing, so with `invert_match` every
+        // remaining line would look like a match.
+        if self.matcher.interrupt().is_set() {
+            return Ok(false);
+        }
+        if self.enabled {
+            let lines = count_line_breaks(mat.bytes()) + 1;
+            self.stats.matched_lines += lines;
+            if self.invert_match {
+                self.stats.matches += lines;
+            } else {
+                self.matcher
+                    .find_iter(mat.bytes(), |_| {
+                        self.stats.matches += 1;
+                        true
+                    })
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+            }
+        }
+        self.inner.matched(searcher, mat)
+    }
+
+    fn context(
+        &mut self,
+        searcher: &Searcher,
+        context: &grep_searcher::SinkContext<'_>,
+    ) -> Result<bool, Self::Error> {
+        if self.matcher.interrupt().is_set() {
+            return Ok(false);
+        }
+        self.inner.context(searcher, context)
+    }
+
+    fn context_break(&mut self, searcher: &Searcher) -> Result<bool, Self::Error> {
+        self.inner.context_break(searcher)
+    }
+
+    fn binary_data(&mut self, searcher: &Searcher, offset: u64) -> Result<bool, Self::Error> {
+        self.inner.binary_data(searcher, offset)
+    }
+
+    fn begin(&mut self, searcher: &Searcher) -> Result<bool, Self::Error> {
+        self.inner.begin(searcher)
+    }
+
+    fn finish(
+        &mut self,
+        searcher: &Searcher,
+        finish: &grep_searcher::SinkFinish,
+    ) -> Result<(), Self::Error> {
+        self.stats.bytes_searched = finish.byte_count();
+        self.stats.binary_offset = finish.binary_byte_offset();
+        self.inner.finish(searcher, finish)
+    }
+}
+
+/// Translates searcher callbacks into [`Match`] and [`ContextLine`] values.
+struct EventSink<'a, E: Emit> {
+    config: &'a Config,
     path: &'a Path,
     matcher: &'a DynMatcher,
-    results: &'a mut Vec<Match>,
-    pending_before: Vec<ContextLine>,
-    last_match_index: Option<usize>,
+    emit: &'a mut E,
     max_count: Option<usize>,
     match_count: usize,
 }
 
-impl<'a> CollectSink<'a> {
+impl<'a, E: Emit> EventSink<'a, E> {
     fn new(
+        config: &'a Config,
         path: &'a Path,
         matcher: &'a DynMatcher,
-        results: &'a mut Vec<Match>,
+        emit: &'a mut E,
         max_count: Option<usize>,
     ) -> Self {
         Self {
+            config,
             path,
             matcher,
-            results,
-            pending_before: Vec::new(),
-            last_match_index: None,
+            emit,
             max_count,
             match_count: 0,
         }
     }
 }
 
-impl<'a> Sink for CollectSink<'a> {
-    type Error = io::Error;
-
-    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
-        let bytes = mat.bytes();
-        let mut submatches = Vec::new();
-        self.matcher
-            .find_iter(bytes, |m| {
-                submatches.push(SubMatch {
-                    start: m.start(),
-                    end: m.end(),
-                });
-                true
-            })
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
-
+impl<'a, E: Emit> EventSink<'a, E> {
+    /// Build the result for a whole matched line (or lines).
+    fn whole_match(
+        &self,
+        bytes: &[u8],
+        line: Option<u64>,
+        byte_offset: u64,
+        submatches: Vec<SubMatch>,
+    ) -> io::Result<Match> {
         let column = submatches.first().map(|m| m.start.saturating_add(1));
-        let line_text = String::from_utf8_lossy(bytes).to_string();
-        let context = std::mem::take(&mut self.pending_before);
-
-        self.results.push(Match {
+        let replacement = match &self.config.replacement {
+            Some(replacement) => {
+                let replaced = replacement.apply(self.matcher, bytes, &submatches)?;
+                Some(String::from_utf8_lossy(&replaced).to_string())
+            }
+            None => None,
+        };
+        Ok(Match {
             path: self.path.to_path_buf(),
-            line: mat.line_number(),
+            line,
+            end_line: line.map(|line| line + count_line_breaks(bytes)),
             column,
+            byte_offset,
             bytes: bytes.to_vec(),
             submatches,
-            line_text,
-            context,
-        });
+            line_text: String::from_utf8_lossy(bytes).to_string(),
+            replacement,
+            context: Vec::new(),
+        })
+    }
 
-        self.last_match_index = Some(self.results.len().saturating_sub(1));
-        self.match_count = self.match_count.saturating_add(1);
-        if let Some(max_count) = self.max_count {
-            if self.match_count >= max_count {
-                return Ok(false);
-            }
+    /// Build one result per submatch, holding only the matched text.
+    fn only_matching(
+        &self,
+        bytes: &[u8],
+        line: Option<u64>,
+        submatches: Vec<SubMatch>,
+    ) -> io::Result<Vec<Match>> {
+        let mut replaced = match &self.config.replacement {
+            Some(replacement) => replacement
+                .each(self.matcher, bytes, &submatches)?
+                .into_iter()
+                .map(|replaced| Some(String::from_utf8_lossy(&replaced).to_string()))
+                .collect(),
+            None => Vec::new(),
         }
+        .into_iter();
+
+        let matches = submatches
+            .into_iter()
+            .map(|submatch| {
+                let (start, end) = (submatch.start, submatch.end);
+                let text = &bytes[start..end];
+                let before = &bytes[..start];
+                let line_start = before
+                    .iter()
+                    .rposition(|&byte| byte == b'\n')
+                    .map_or(0, |pos| pos + 1);
+                let line = line.map(|line| line + count_line_breaks(before));
+                let captures = submatch
+                    .captures
+                    .into_iter()
+                    .map(|capture| {
+                        capture.map(|capture| Capture {
+                            start: capture.start - start,
+                            end: capture.end - start,
+                            ..capture
+                        })
+                    })
+                    .collect();
+                Match {
+                    path: self.path.to_path_buf(),
+                    line,
+                    end_line: line.map(|line| line + count_line_breaks(text)),
+                    column: Some(start - line_start + 1),
+                    byte_offset: submatch.byte_offset,
+                    bytes: text.to_vec(),
+                    submatches: vec![SubMatch {
+                        start: 0,
+                        end: end - start,
+                        byte_offset: submatch.byte_offset,
+                        pattern: submatch.pattern,
+                        captures,
+                    }],
+                    line_text: String::from_utf8_lossy(text).to_string(),
+                    replacement: replaced.next().flatten(),
+                    context: Vec::new(),
+                }
+            })
+            .collect();
+        Ok(matches)
+    }
+}
 
-        Ok(true)
+impl<'a, E: Emit> Sink for EventSink<'a, E> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        let bytes = mat.bytes();
+        let offset = mat.absolute_byte_offset();
+        let submatches = find_submatches(self.matcher, bytes, offset)?;
+        if self.matcher.interrupt().is_set() {
+            return Ok(false);
+        }
+        let line = mat.line_number();
+
+        let matches = if self.config.only_matching {
+            self.only_matching(bytes, line, submatches)?
+        } else {
+            vec![self.whole_match(bytes, line, offset, submatches)?]
+        };
+
+        self.match_count = self.match_count.saturating_add(1);
+        let mut keep_going = matches.into_iter().all(|mat| self.emit.matched(mat));
+        if self
+            .max_count
+            .is_some_and(|max_count| self.match_count >= max_count)
+        {
+            keep_going = false;
+        }
+        Ok(keep_going)
     }
 
     fn context(
@@ -387,148 +1181,435 @@ impl<'a> Sink for CollectSink<'a> {
             path: self.path.to_path_buf(),
             kind,
             line: context.line_number(),
+            byte_offset: context.absolute_byte_offset(),
             bytes: context.bytes().to_vec(),
             line_text: String::from_utf8_lossy(context.bytes()).to_string(),
         };
 
-        match kind {
-            ContextKind::Before => self.pending_before.push(line),
-            ContextKind::After | ContextKind::Other => {
-                if let Some(index) = self.last_match_index {
-                    if let Some(existing) = self.results.get_mut(index) {
-                        existing.context.push(line);
-                    }
+        Ok(self.emit.context(line))
+    }
+
+    fn context_break(&mut self, _searcher: &Searcher) -> Result<bool, Self::Error> {
+        Ok(self.emit.context_break())
+    }
+
+    fn binary_data(&mut self, _searcher: &Searcher, offset: u64) -> Result<bool, Self::Error> {
+        Ok(self.emit.binary(self.path, offset))
+    }
+
+    fn finish(
+        &mut self,
+        _searcher: &Searcher,
+        _finish: &grep_searcher::SinkFinish,
+    ) -> Result<(), Self::Error> {
+        self.emit.finish();
+        Ok(())
+    }
+}
+
+enum Event {
+    Match(Match),
+    Context(ContextLine),
+    ContextBreak,
+    Binary(PathBuf, u64),
+    Finish,
+    Error(FileError),
+}
+
+/// Buffers the results of a single file so they can be handed from a
+/// parallel worker to the consuming thread.
+#[derive(Default)]
+struct FileEvents(Vec<Event>);
+
+impl FileEvents {
+    /// Deliver the buffered events to `emit`, honoring its request to stop
+    /// the file just as the searcher would have. Errors are handled as in a
+    /// sequential walk, failing in strict mode.
+    fn replay<E: Emit>(self, config: &Config, emit: &mut E) -> Result<(), SearchError> {
+        let mut keep_going = true;
+        for event in self.0 {
+            match event {
+                Event::Match(mat) if keep_going => keep_going = emit.matched(mat),
+                Event::Context(line) if keep_going => keep_going = emit.context(line),
+                Event::ContextBreak if keep_going => keep_going = emit.context_break(),
+                Event::Binary(path, offset) if keep_going => {
+                    keep_going = emit.binary(&path, offset);
                 }
+                Event::Finish => emit.finish(),
+                Event::Error(error) => recover(config, error, |error| emit.error(error))?,
+                Event::Match(_) | Event::Context(_) | Event::ContextBreak | Event::Binary(..) => {}
             }
         }
+        Ok(())
+    }
+}
 
-        Ok(true)
+impl Emit for FileEvents {
+    fn matched(&mut self, mat: Match) -> bool {
+        self.0.push(Event::Match(mat));
+        true
+    }
+
+    fn context(&mut self, line: ContextLine) -> bool {
+        self.0.push(Event::Context(line));
+        true
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.0.push(Event::ContextBreak);
+        true
+    }
+
+    fn binary(&mut self, path: &Path, offset: u64) -> bool {
+        self.0.push(Event::Binary(path.to_path_buf(), offset));
+        true
+    }
+
+    fn finish(&mut self) {
+        self.0.push(Event::Finish);
     }
 }
 
-struct CountSink {
-    count: u64,
-    max_count: Option<usize>,
+/// Replays the buffered results of parallel workers into an [`Emit`],
+/// stopping with the error that fails a strict search in `failed`.
+struct Replay<'a, E: Emit> {
+    config: &'a Config,
+    emit: &'a mut E,
+    failed: &'a mut Option<SearchError>,
 }
 
-impl CountSink {
-    fn new(max_count: Option<usize>) -> Self {
+impl<'a, E: Emit> Consume<FileEvents> for Replay<'a, E> {
+    fn output(&mut self, _path: PathBuf, events: FileEvents) -> bool {
+        if let Err(err) = events.replay(self.config, self.emit) {
+            *self.failed = Some(err);
+            return false;
+        }
+        !self.emit.quit()
+    }
+
+    fn error(&mut self, error: &FileError) {
+        self.emit.error(error);
+    }
+}
+
+/// Forwards results to a user supplied [`MatchSink`].
+struct SinkEmit<'a, S: MatchSink>(&'a mut S);
+
+impl<'a, S: MatchSink> Emit for SinkEmit<'a, S> {
+    fn matched(&mut self, mat: Match) -> bool {
+        self.0.matched(&mat)
+    }
+
+    fn context(&mut self, line: ContextLine) -> bool {
+        self.0.context(&line)
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.0.context_break()
+    }
+
+    fn binary(&mut self, path: &Path, offset: u64) -> bool {
+        self.0.binary(path, offset)
+    }
+
+    fn finish(&mut self) {
+        self.0.finish();
+    }
+
+    fn error(&mut self, error: &FileError) {
+        self.0.error(error);
+    }
+}
+
+/// Attaches context lines to the match they surround before handing the
+/// match to `inner`. A match is held back until the next match, context
+/// break or the end of the file so that its after-context is complete.
+///
+/// When disabled, every event is passed through to `inner` unchanged.
+pub(crate) struct Attach<E: Emit> {
+    inner: E,
+    enabled: bool,
+    pending: Option<Match>,
+    pending_before: Vec<ContextLine>,
+}
+
+impl<F: FnMut(Match) -> bool> Attach<Output<F>> {
+    pub(crate) fn new(out: F) -> Self {
+        Self::around(
+            Output {
+                out,
+                stopped: false,
+            },
+            true,
+        )
+    }
+}
+
+impl<E: Emit> Attach<E> {
+    pub(crate) fn around(inner: E, enabled: bool) -> Self {
         Self {
-            count: 0,
-            max_count,
+            inner,
+            enabled,
+            pending: None,
+            pending_before: Vec::new(),
         }
     }
 
-    fn count(&self) -> u64 {
-        self.count
+    fn flush(&mut self) -> bool {
+        match self.pending.take() {
+            Some(mat) => self.inner.matched(mat),
+            None => true,
+        }
     }
 }
 
-impl Sink for CountSink {
-    type Error = io::Error;
+impl<E: Emit> Emit for Attach<E> {
+    fn matched(&mut self, mut mat: Match) -> bool {
+        if !self.enabled {
+            return self.inner.matched(mat);
+        }
+        let keep_going = self.flush();
+        mat.context = std::mem::take(&mut self.pending_before);
+        self.pending = Some(mat);
+        keep_going
+    }
 
-    fn matched(&mut self, _searcher: &Searcher, _mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
-        self.count = self.count.saturating_add(1);
-        if let Some(max_count) = self.max_count {
-            if self.count >= max_count as u64 {
-                return Ok(false);
+    fn context(&mut self, line: ContextLine) -> bool {
+        if !self.enabled {
+            return self.inner.context(line);
+        }
+        match line.kind {
+            ContextKind::Before => self.pending_before.push(line),
+            ContextKind::After | ContextKind::Other => {
+                if let Some(pending) = self.pending.as_mut() {
+                    pending.context.push(line);
+                }
             }
         }
-        Ok(true)
+        true
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.flush() && self.inner.context_break()
+    }
+
+    fn binary(&mut self, path: &Path, offset: u64) -> bool {
+        self.inner.binary(path, offset)
+    }
+
+    fn finish(&mut self) {
+        self.flush();
+        self.pending_before.clear();
+        self.inner.finish();
+    }
+
+    fn error(&mut self, error: &FileError) {
+        self.inner.error(error);
+    }
+
+    fn quit(&self) -> bool {
+        self.inner.quit()
     }
 }
 
-struct CallbackSink<'a, S: MatchSink> {
-    path: &'a Path,
-    matcher: &'a DynMatcher,
-    sink: &'a mut S,
-    max_count: Option<usize>,
-    match_count: usize,
+/// Hands finished matches to a closure, ignoring everything else. Once the
+/// closure returns `false` the whole walk stops.
+pub(crate) struct Output<F: FnMut(Match) -> bool> {
+    out: F,
+    stopped: bool,
 }
 
-impl<'a, S: MatchSink> CallbackSink<'a, S> {
-    fn new(
-        path: &'a Path,
-        matcher: &'a DynMatcher,
-        sink: &'a mut S,
-        max_count: Option<usize>,
-    ) -> Self {
-        Self {
-            path,
-            matcher,
-            sink,
-            max_count,
-            match_count: 0,
+impl<F: FnMut(Match) -> bool> Emit for Output<F> {
+    fn matched(&mut self, mat: Match) -> bool {
+        if !self.stopped && !(self.out)(mat) {
+            self.stopped = true;
         }
+        !self.stopped
+    }
+
+    fn context(&mut self, _line: ContextLine) -> bool {
+        true
+    }
+
+    fn quit(&self) -> bool {
+        self.stopped
     }
 }
 
-impl<'a, S: MatchSink> Sink for CallbackSink<'a, S> {
-    type Error = io::Error;
+/// Groups each file's matches and context into hunks of contiguous lines.
+#[derive(Default)]
+struct Group {
+    files: Vec<FileResult>,
+    path: Option<PathBuf>,
+    hunks: Vec<Hunk>,
+    open: bool,
+    matched: bool,
+}
 
-    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
-        let bytes = mat.bytes();
-        let mut submatches = Vec::new();
-        self.matcher
-            .find_iter(bytes, |m| {
-                submatches.push(SubMatch {
-                    start: m.start(),
-                    end: m.end(),
-                });
-                true
-            })
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+impl Group {
+    fn push(&mut self, path: &Path, line: Option<u64>, end_line: Option<u64>, item: HunkLine) {
+        if self.path.is_none() {
+            self.path = Some(path.to_path_buf());
+        }
+        // Without context the searcher sends no breaks, so lines that are not
+        // adjacent to the open hunk start a new one.
+        if let (
+            Some(line),
+            Some(Hunk {
+                end_line: Some(end),
+                ..
+            }),
+        ) = (line, self.hunks.last())
+            && line > end + 1
+        {
+            self.open = false;
+        }
+        if !self.open {
+            self.hunks.push(Hunk {
+                start_line: line,
+                end_line,
+                lines: Vec::new(),
+            });
+            self.open = true;
+        }
+        let hunk = self.hunks.last_mut().expect("an open hunk");
+        hunk.end_line = hunk.end_line.max(end_line);
+        hunk.lines.push(item);
+    }
+}
 
-        let column = submatches.first().map(|m| m.start.saturating_add(1));
-        let line_text = String::from_utf8_lossy(bytes).to_string();
-        let mat = Match {
-            path: self.path.to_path_buf(),
-            line: mat.line_number(),
-            column,
-            bytes: bytes.to_vec(),
-            submatches,
-            line_text,
-            context: Vec::new(),
-        };
+impl Emit for Group {
+    fn matched(&mut self, mat: Match) -> bool {
+        self.matched = true;
+        let path = mat.path.clone();
+        self.push(&path, mat.line, mat.end_line, HunkLine::Match(mat));
+        true
+    }
 
-        self.match_count = self.match_count.saturating_add(1);
-        let mut keep_going = self.sink.matched(&mat);
-        if let Some(max_count) = self.max_count {
-            if self.match_count >= max_count {
-                keep_going = false;
-            }
+    fn context(&mut self, line: ContextLine) -> bool {
+        let path = line.path.clone();
+        self.push(&path, line.line, line.line, HunkLine::Context(line));
+        true
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.open = false;
+        true
+    }
+
+    fn finish(&mut self) {
+        let hunks = std::mem::take(&mut self.hunks);
+        if let Some(path) = self.path.take()
+            && self.matched
+        {
+            self.files.push(FileResult { path, hunks });
         }
-        Ok(keep_going)
+        self.open = false;
+        self.matched = false;
     }
+}
 
-    fn context(
-        &mut self,
-        _searcher: &Searcher,
-        context: &grep_searcher::SinkContext<'_>,
-    ) -> Result<bool, Self::Error> {
-        let kind = match context.kind() {
-            grep_searcher::SinkContextKind::Before => ContextKind::Before,
-            grep_searcher::SinkContextKind::After => ContextKind::After,
-            grep_searcher::SinkContextKind::Other => ContextKind::Other,
-        };
-        let line = ContextLine {
-            path: self.path.to_path_buf(),
-            kind,
-            line: context.line_number(),
-            bytes: context.bytes().to_vec(),
-            line_text: String::from_utf8_lossy(context.bytes()).to_string(),
-        };
+/// Enforces the global result cap configured through `limit`.
+pub(crate) struct Limit<'a, E: Emit> {
+    inner: &'a mut E,
+    remaining: Option<usize>,
+}
 
-        Ok(self.sink.context(&line))
+impl<'a, E: Emit> Limit<'a, E> {
+    pub(crate) fn new(limit: Option<usize>, inner: &'a mut E) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+        }
     }
 
-    fn finish(
-        &mut self,
-        _searcher: &Searcher,
-        _finish: &grep_searcher::SinkFinish,
-    ) -> Result<(), Self::Error> {
-        self.sink.finish();
-        Ok(())
+    fn exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+impl<'a, E: Emit> Emit for Limit<'a, E> {
+    fn matched(&mut self, mat: Match) -> bool {
+        if self.exhausted() {
+            return false;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        self.inner.matched(mat) && !self.exhausted()
+    }
+
+    fn context(&mut self, line: ContextLine) -> bool {
+        self.inner.context(line)
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.inner.context_break()
+    }
+
+    fn binary(&mut self, path: &Path, offset: u64) -> bool {
+        self.inner.binary(path, offset)
+    }
+
+    fn finish(&mut self) {
+        self.inner.finish();
+    }
+
+    fn error(&mut self, error: &FileError) {
+        self.inner.error(error);
+    }
+
+    fn quit(&self) -> bool {
+        self.exhausted() || self.inner.quit()
+    }
+}
+
+/// Counts matching lines, or every match when given a matcher.
+struct CountSink<'a> {
+    matcher: Option<&'a DynMatcher>,
+    lines: u64,
+    matches: u64,
+    max_count: Option<usize>,
+}
+
+impl<'a> CountSink<'a> {
+    fn new(matcher: Option<&'a DynMatcher>, max_count: Option<usize>) -> Self {
+        Self {
+            matcher,
+            lines: 0,
+            matches: 0,
+            max_count,
+        }
+    }
+
+    fn count(&self) -> u64 {
+        match self.matcher {
+            Some(_) => self.matches,
+            None => self.lines,
+        }
+    }
+}
+
+impl Sink for CountSink<'_> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        self.lines = self.lines.saturating_add(1);
+        if let Some(matcher) = self.matcher {
+            matcher
+                .find_iter(mat.bytes(), |_| {
+                    self.matches = self.matches.saturating_add(1);
+                    true
+                })
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        if self
+            .max_count
+            .is_some_and(|max_count| self.lines >= max_count as u64)
+        {
+            return Ok(false);
+        }
+        Ok(true)
     }
 }
 
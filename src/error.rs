@@ -1,12 +1,66 @@
 use std::fmt;
+use std::path::PathBuf;
+
+use crate::types::{FileResult, Match};
 
 #[derive(Debug)]
 pub enum SearchError {
     InvalidPattern(String),
     InvalidGlob(String),
     InvalidType(String),
+    InvalidReplacement(String),
+    InvalidEncoding(String),
     Walk(ignore::Error),
     Io(std::io::Error),
+    /// The search was stopped through its
+    /// [`CancellationToken`](crate::CancellationToken). Methods collecting
+    /// their results carry what they had collected in `partial`.
+    Cancelled {
+        partial: Option<Partial>,
+    },
+    /// The search ran past its [`deadline`](crate::SearchBuilder::deadline).
+    /// Methods collecting their results carry what they had collected in
+    /// `partial`.
+    TimedOut {
+        partial: Option<Partial>,
+    },
+}
+
+/// The results a collecting method had gathered when its search was
+/// [cancelled](SearchError::Cancelled) or [timed out](SearchError::TimedOut).
+#[derive(Debug)]
+pub enum Partial {
+    /// From `count`.
+    Count(u64),
+    /// From `count_per_file`.
+    Counts(Vec<(PathBuf, u64)>),
+    /// From `files_with_matches`, `files_without_match` and `walk_files`.
+    Files(Vec<PathBuf>),
+    /// From `file_results`.
+    FileResults(Vec<FileResult>),
+    /// From `search_reader` and `search_slice`.
+    Matches(Vec<Match>),
+}
+
+impl SearchError {
+    /// Return `value` if `result` is `Ok`, and otherwise attach it as the
+    /// partial results of a cancellation or timeout.
+    pub(crate) fn keep_partial<T>(
+        result: Result<(), SearchError>,
+        value: T,
+        wrap: impl FnOnce(T) -> Partial,
+    ) -> Result<T, SearchError> {
+        match result {
+            Ok(()) => Ok(value),
+            Err(Self::Cancelled { .. }) => Err(Self::Cancelled {
+                partial: Some(wrap(value)),
+            }),
+            Err(Self::TimedOut { .. }) => Err(Self::TimedOut {
+                partial: Some(wrap(value)),
+            }),
+            Err(err) => Err(err),
+        }
+    }
 }
 
 impl fmt::Display for SearchError {
@@ -15,8 +69,12 @@ impl fmt::Display for SearchError {
             Self::InvalidPattern(message) => write!(f, "invalid pattern: {message}"),
             Self::InvalidGlob(message) => write!(f, "invalid glob: {message}"),
             Self::InvalidType(message) => write!(f, "invalid type: {message}"),
+            Self::InvalidReplacement(message) => write!(f, "invalid replacement: {message}"),
+            Self::InvalidEncoding(label) => write!(f, "unknown encoding: {label}"),
             Self::Walk(err) => write!(f, "walk error: {err}"),
             Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Cancelled { .. } => write!(f, "search cancelled"),
+            Self::TimedOut { .. } => write!(f, "search timed out"),
         }
     }
 }
@@ -24,7 +82,13 @@ impl fmt::Display for SearchError {
 impl std::error::Error for SearchError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Self::InvalidPattern(_) | Self::InvalidGlob(_) | Self::InvalidType(_) => None,
+            Self::InvalidPattern(_)
+            | Self::InvalidGlob(_)
+            | Self::InvalidType(_)
+            | Self::InvalidReplacement(_)
+            | Self::InvalidEncoding(_)
+            | Self::Cancelled { .. }
+            | Self::TimedOut { .. } => None,
             Self::Walk(err) => Some(err),
             Self::Io(err) => Some(err),
         }
@@ -42,3 +106,57 @@ impl From<std::io::Error> for SearchError {
         Self::Io(err)
     }
 }
+
+/// An error confined to a single file or directory during a walk.
+///
+/// Unless the search is [`strict`](crate::SearchBuilder::strict), these are
+/// handed to [`MatchSink::error`](crate::MatchSink::error) and any attached
+/// [`SearchReport`](crate::SearchReport) while the search keeps going.
+#[derive(Debug)]
+pub struct FileError {
+    /// The file or directory involved, when known.
+    pub path: Option<PathBuf>,
+    pub error: SearchError,
+}
+
+impl FileError {
+    pub(crate) fn new(path: PathBuf, error: SearchError) -> Self {
+        Self {
+            path: Some(path),
+            error,
+        }
+    }
+
+    pub(crate) fn walk(err: ignore::Error) -> Self {
+        Self {
+            path: walk_error_path(&err),
+            error: SearchError::Walk(err),
+        }
+    }
+}
+
+fn walk_error_path(err: &ignore::Error) -> Option<PathBuf> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path.clone()),
+        ignore::Error::Loop { child, .. } => Some(child.clone()),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            walk_error_path(err)
+        }
+        _ => None,
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
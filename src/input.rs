@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use grep_cli::{CommandReaderBuilder, DecompressionReaderBuilder};
+use grep_searcher::{Searcher, Sink};
+#[cfg(feature = "archives")]
+use ignore::overrides::Override;
+#[cfg(feature = "archives")]
+use ignore::types::Types;
+#[cfg(feature = "archives")]
+use zip::ZipArchive;
+
+use crate::config::Config;
+#[cfg(feature = "archives")]
+use crate::engine;
+use crate::error::{FileError, SearchError};
+use crate::matcher::EngineMatcher;
+
+/// Opens the transformed contents of a file.
+type OpenFn = dyn Fn(&Path) -> io::Result<Box<dyn Read>> + Send + Sync;
+
+/// Transforms a file before it is searched, like `rg --pre`.
+#[derive(Clone)]
+pub(crate) enum Preprocessor {
+    /// An external command run with the file path as its only argument and
+    /// the file on stdin. Its stdout is searched.
+    Command(PathBuf),
+    /// A closure opening the transformed contents of a file.
+    With(Arc<OpenFn>),
+}
+
+impl fmt::Debug for Preprocessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Command(program) => f.debug_tuple("Command").field(program).finish(),
+            Self::With(_) => f.debug_tuple("With").field(&"<closure>").finish(),
+        }
+    }
+}
+
+impl Preprocessor {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        match self {
+            Self::Command(program) => {
+                let mut command = Command::new(program);
+                command.arg(path).stdin(File::open(path)?);
+                let reader = CommandReaderBuilder::new()
+                    .build(&mut command)
+                    .map_err(|err| io::Error::other(format!("{}: {err}", program.display())))?;
+                Ok(Box::new(reader))
+            }
+            Self::With(open) => open(path),
+        }
+    }
+}
+
+/// Globs naming the archives whose members are searched by
+/// [`search_archives`](crate::SearchBuilder::search_archives).
+#[cfg(feature = "archives")]
+const ARCHIVE_GLOBS: &[&str] = &[
+    "*.zip",
+    "*.tar",
+    "*.tar.gz",
+    "*.tgz",
+    "*.tar.bz2",
+    "*.tbz2",
+    "*.tar.xz",
+    "*.txz",
+    "*.tar.zst",
+];
+
+/// Globs the walker must select on top of the configured ones so that
+/// archives reach the archive reader.
+pub(crate) fn archive_globs(config: &Config) -> &'static [&'static str] {
+    #[cfg(feature = "archives")]
+    if config.search_archives {
+        return ARCHIVE_GLOBS;
+    }
+    let _ = config;
+    &[]
+}
+
+#[cfg(feature = "archives")]
+#[derive(Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+}
+
+/// Turns a walked path into the bytes the searcher sees, running the
+/// configured preprocessor, decompressing files or opening archives when
+/// asked to.
+pub(crate) struct Inputs {
+    preprocessor: Option<(Preprocessor, Option<GlobSet>)>,
+    decompress: Option<DecompressionReaderBuilder>,
+    #[cfg(feature = "archives")]
+    archives: Option<Members>,
+}
+
+/// Decides which archive members are searched, applying the configured globs
+/// and file types to the member paths.
+#[cfg(feature = "archives")]
+struct Members {
+    overrides: Option<Override>,
+    types: Option<Types>,
+    decompress: DecompressionReaderBuilder,
+}
+
+#[cfg(feature = "archives")]
+impl Members {
+    fn selected(&self, member: &Path) -> bool {
+        let overridden = self
+            .overrides
+            .as_ref()
+            .is_some_and(|overrides| overrides.matched(member, false).is_ignore());
+        let untyped = self
+            .types
+            .as_ref()
+            .is_some_and(|types| types.matched(member, false).is_ignore());
+        !overridden && !untyped
+    }
+
+    /// Search every selected member of the archive at `path`.
+    fn search_archive<F, K>(
+        &self,
+        path: &Path,
+        kind: ArchiveKind,
+        mut search: F,
+        mut skip: K,
+    ) -> Result<(), SearchError>
+    where
+        F: FnMut(&Path, Input<'_>) -> Result<(), SearchError>,
+        K: FnMut(FileError) -> Result<(), SearchError>,
+    {
+        match kind {
+            ArchiveKind::Zip => {
+                let mut archive = ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+                for index in 0..archive.len() {
+                    let listed = archive
+                        .name_for_index(index)
+                        .and_then(Result::ok)
+                        .map(|name| PathBuf::from(&*name));
+                    let mut member = match archive.by_index(index) {
+                        Ok(member) => member,
+                        Err(_) if listed.as_deref().is_some_and(|name| !self.selected(name)) => {
+                            continue;
+                        }
+                        Err(err) => {
+                            let failed = match &listed {
+                                Some(name) => member_path(path, name),
+                                None => path.to_path_buf(),
+                            };
+                            skip(FileError::new(failed, io::Error::other(err).into()))?;
+                            continue;
+                        }
+                    };
+                    let Some(name) = member.enclosed_name() else {
+                        continue;
+                    };
+                    if member.is_file() && self.selected(&name) {
+                        let virtual_path = member_path(path, &name);
+                        if let Err(err) = search(&virtual_path, Input::Member(&mut member)) {
+                            skip(FileError::new(virtual_path, err))?;
+                        }
+                    }
+                }
+            }
+            ArchiveKind::Tar => {
+                let reader: Box<dyn Read> = if self.decompress.get_matcher().has_command(path) {
+                    Box::new(self.decompress.build(path).map_err(io::Error::from)?)
+                } else {
+                    Box::new(File::open(path)?)
+                };
+                // Tar members are read in sequence, so a broken entry ends
+                // the archive.
+                let mut archive = tar::Archive::new(reader);
+                for member in archive.entries()? {
+                    let mut member = member?;
+                    if !member.header().entry_type().is_file() {
+                        continue;
+                    }
+                    let name = member.path()?.into_owned();
+                    if self.selected(&name) {
+                        let virtual_path = member_path(path, &name);
+                        if let Err(err) = search(&virtual_path, Input::Member(&mut member)) {
+                            skip(FileError::new(virtual_path, err))?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single input to search: a file on disk or an archive member.
+pub(crate) enum Input<'a> {
+    File(&'a Inputs, &'a Path),
+    #[cfg(feature = "archives")]
+    Member(&'a mut dyn Read),
+}
+
+impl Input<'_> {
+    pub(crate) fn search<S>(
+        self,
+        searcher: &mut Searcher,
+        matcher: &EngineMatcher,
+        sink: S,
+    ) -> io::Result<()>
+    where
+        S: Sink<Error = io::Error>,
+    {
+        match self {
+            Self::File(inputs, path) => inputs.search_path(searcher, matcher, path, sink),
+            #[cfg(feature = "archives")]
+            Self::Member(reader) => searcher.search_reader(matcher, reader, sink),
+        }
+    }
+}
+
+impl Inputs {
+    pub(crate) fn new(config: &Config) -> Result<Arc<Self>, SearchError> {
+        if let Some(compiled) = &config.compiled {
+            return Ok(Arc::clone(&compiled.inputs));
+        }
+        let preprocessor = match &config.preprocessor {
+            Some(preprocessor) => Some((preprocessor.clone(), pre_globs(&config.pre_globs)?)),
+            None => None,
+        };
+        #[cfg(feature = "archives")]
+        let archives = if config.search_archives {
+            Some(Members {
+                overrides: engine::build_overrides(config, &[])?,
+                types: engine::build_types(config, &[])?,
+                decompress: DecompressionReaderBuilder::new(),
+            })
+        } else {
+            None
+        };
+        Ok(Arc::new(Self {
+            preprocessor,
+            decompress: config.search_zip.then(DecompressionReaderBuilder::new),
+            #[cfg(feature = "archives")]
+            archives,
+        }))
+    }
+
+    /// Call `search` with every input behind `path` and the path to report
+    /// it under: the file itself, or each selected member of an archive as
+    /// `archive!/member`. A member that cannot be read or searched is handed
+    /// to `skip`, and the rest of the archive is still searched unless `skip`
+    /// returns an error.
+    pub(crate) fn search_each<F, K>(
+        &self,
+        path: &Path,
+        mut search: F,
+        #[cfg_attr(not(feature = "archives"), allow(unused_variables))] skip: K,
+    ) -> Result<(), SearchError>
+    where
+        F: FnMut(&Path, Input<'_>) -> Result<(), SearchError>,
+        K: FnMut(FileError) -> Result<(), SearchError>,
+    {
+        #[cfg(feature = "archives")]
+        if let Some(members) = &self.archives
+            && let Some(kind) = archive_kind(path)
+        {
+            return members.search_archive(path, kind, search, skip);
+        }
+        search(path, Input::File(self, path))
+    }
+
+    /// Search the file at `path` with `sink`. A preprocessor takes
+    /// precedence over decompression.
+    fn search_path<S>(
+        &self,
+        searcher: &mut Searcher,
+        matcher: &EngineMatcher,
+        path: &Path,
+        sink: S,
+    ) -> io::Result<()>
+    where
+        S: Sink<Error = io::Error>,
+    {
+        if let Some((preprocessor, globs)) = &self.preprocessor
+            && globs.as_ref().is_none_or(|globs| globs.is_match(path))
+        {
+            let reader = preprocessor.open(path)?;
+            return searcher.search_reader(matcher, reader, sink);
+        }
+        if let Some(decompress) = &self.decompress
+            && decompress.get_matcher().has_command(path)
+        {
+            let reader = decompress.build(path)?;
+            return searcher.search_reader(matcher, reader, sink);
+        }
+        searcher.search_path(matcher, path, sink)
+    }
+}
+
+#[cfg(feature = "archives")]
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        return Some(ArchiveKind::Zip);
+    }
+    ARCHIVE_GLOBS
+        .iter()
+        .filter_map(|glob| glob.strip_prefix('*'))
+        .any(|suffix| name.ends_with(suffix))
+        .then_some(ArchiveKind::Tar)
+}
+
+/// The virtual path of `member` inside `archive`, e.g. `bundle.zip!/src/main.rs`.
+#[cfg(feature = "archives")]
+fn member_path(archive: &Path, member: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push("!/");
+    path.push(member.as_os_str());
+    PathBuf::from(path)
+}
+
+/// Build the `pre_glob` set, or `None` when every file is preprocessed.
+fn pre_globs(globs: &[String]) -> Result<Option<GlobSet>, SearchError> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|err| SearchError::InvalidGlob(err.to_string()))?);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|err| SearchError::InvalidGlob(err.to_string()))
+}
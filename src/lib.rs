@@ -1,19 +1,35 @@
 #![doc = include_str!("../README.md")]
 
 mod builder;
+mod cancel;
+mod compiled;
 mod config;
 mod engine;
 mod error;
+mod input;
 mod matcher;
+mod replace;
+mod report;
+mod rewrite;
 mod search;
 mod sink;
+#[cfg(feature = "tokio")]
+mod stream;
 mod types;
 
 pub use builder::SearchBuilder;
-pub use error::SearchError;
+pub use cancel::CancellationToken;
+pub use compiled::CompiledSearch;
+pub use error::{FileError, Partial, SearchError};
+pub use report::{SearchReport, SearchStats};
 pub use search::Search;
 pub use sink::MatchSink;
-pub use types::{ContextKind, ContextLine, Match, SubMatch};
+#[cfg(feature = "tokio")]
+pub use stream::SearchStream;
+pub use types::{
+    BinaryMode, Capture, ContextKind, ContextLine, FileResult, FileRewrite, Hunk, HunkLine, Match,
+    Order, RewriteReport, SortKey, SubMatch,
+};
 
 /// Create a new SearchBuilder with rg-style defaults.
 pub fn rg(pattern: impl Into<String>) -> SearchBuilder {
@@ -1,16 +1,104 @@
-use crate::config::{CaseMode, Config, RegexEngine};
+use crate::cancel::Interrupt;
+use crate::config::{CaseMode, Config, PatternSource, RegexEngine};
 use crate::error::SearchError;
-use grep_matcher::{Match, Matcher, NoCaptures};
-use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_matcher::{Captures, Match, Matcher};
+use grep_regex::{RegexCaptures, RegexMatcher, RegexMatcherBuilder};
+use regex_automata::util::syntax;
+use regex_automata::{Anchored, Input, MatchKind, PatternSet, meta};
+use regex_syntax::ast;
+use std::convert::Infallible;
+use std::sync::{Arc, OnceLock};
 
 #[cfg(feature = "pcre2")]
-use grep_pcre2::{RegexMatcher as Pcre2Matcher, RegexMatcherBuilder as Pcre2MatcherBuilder};
+use grep_pcre2::{
+    RegexCaptures as Pcre2Captures, RegexMatcher as Pcre2Matcher,
+    RegexMatcherBuilder as Pcre2MatcherBuilder,
+};
 
-pub(crate) fn build_matcher(pattern: &str, config: &Config) -> Result<EngineMatcher, SearchError> {
+pub(crate) fn build_matcher(config: &Config) -> Result<EngineMatcher, SearchError> {
+    if let Some(compiled) = &config.compiled {
+        return Ok(EngineMatcher {
+            interrupt: Interrupt::new(config),
+            ..compiled.matcher.clone()
+        });
+    }
+    let patterns = load_patterns(config)?;
+    let inner = build_inner(&patterns, config)?;
+    let group_names = if config.fixed_strings {
+        Vec::new()
+    } else {
+        group_names(&patterns, &inner)
+    };
+    let attribution = (patterns.len() > 1).then(|| Arc::new(Attribution::new(patterns, config)));
+
+    Ok(EngineMatcher {
+        inner,
+        attribution,
+        group_names,
+        interrupt: Interrupt::new(config),
+    })
+}
+
+/// Map every capture group index to its name, if it has one.
+///
+/// Neither engine can enumerate its group names, so candidate names are
+/// scanned from the pattern text and then resolved through the matcher,
+/// which discards anything that only looked like a named group.
+fn group_names(patterns: &[String], matcher: &InnerMatcher) -> Vec<Option<String>> {
+    let mut names = vec![None; matcher.capture_count()];
+    for pattern in patterns {
+        let mut rest = pattern.as_str();
+        while let Some(open) = rest.find("(?") {
+            rest = &rest[open + 2..];
+            let (body, close) = if let Some(body) = rest.strip_prefix("P<") {
+                (body, '>')
+            } else if let Some(body) = rest.strip_prefix('<') {
+                (body, '>')
+            } else if let Some(body) = rest.strip_prefix('\'') {
+                (body, '\'')
+            } else {
+                continue;
+            };
+            let Some(end) = body.find(close) else {
+                continue;
+            };
+            let name = &body[..end];
+            if let Some(slot) = matcher
+                .capture_index(name)
+                .and_then(|index| names.get_mut(index))
+            {
+                *slot = Some(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Resolve the configured pattern sources into the final list of patterns,
+/// reading pattern files one pattern per line.
+fn load_patterns(config: &Config) -> Result<Vec<String>, SearchError> {
+    let mut patterns = Vec::new();
+    for source in &config.patterns {
+        match source {
+            PatternSource::Pattern(pattern) => patterns.push(pattern.clone()),
+            PatternSource::File(path) => {
+                let contents = std::fs::read_to_string(path)?;
+                patterns.extend(
+                    contents
+                        .lines()
+                        .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()),
+                );
+            }
+        }
+    }
+    Ok(patterns)
+}
+
+fn build_inner(patterns: &[String], config: &Config) -> Result<InnerMatcher, SearchError> {
     match config.engine {
-        RegexEngine::Default => build_default_matcher(pattern, config),
+        RegexEngine::Default => build_default_matcher(patterns, config),
         #[cfg(feature = "pcre2")]
-        RegexEngine::Pcre2 => build_pcre2_matcher(pattern, config),
+        RegexEngine::Pcre2 => build_pcre2_matcher(patterns, config),
     }
 }
 
@@ -26,7 +114,10 @@ fn apply_case(builder: &mut RegexMatcherBuilder, mode: CaseMode) {
     }
 }
 
-fn build_default_matcher(pattern: &str, config: &Config) -> Result<EngineMatcher, SearchError> {
+fn build_default_matcher(
+    patterns: &[String],
+    config: &Config,
+) -> Result<InnerMatcher, SearchError> {
     let mut builder = RegexMatcherBuilder::new();
     apply_case(&mut builder, config.case_mode);
     if config.fixed_strings {
@@ -38,15 +129,19 @@ fn build_default_matcher(pattern: &str, config: &Config) -> Result<EngineMatcher
     if config.line_regexp {
         builder.whole_line(true);
     }
+    if config.multiline {
+        builder.multi_line(true);
+        builder.dot_matches_new_line(config.multiline_dotall);
+    }
 
     let matcher = builder
-        .build(pattern)
+        .build_many(patterns)
         .map_err(|err| SearchError::InvalidPattern(err.to_string()))?;
-    Ok(EngineMatcher::Regex(matcher))
+    Ok(InnerMatcher::Regex(matcher))
 }
 
 #[cfg(feature = "pcre2")]
-fn build_pcre2_matcher(pattern: &str, config: &Config) -> Result<EngineMatcher, SearchError> {
+fn build_pcre2_matcher(patterns: &[String], config: &Config) -> Result<InnerMatcher, SearchError> {
     let mut builder = Pcre2MatcherBuilder::new();
     match config.case_mode {
         CaseMode::Smart => {
@@ -66,54 +161,386 @@ fn build_pcre2_matcher(pattern: &str, config: &Config) -> Result<EngineMatcher,
     if config.line_regexp {
         builder.whole_line(true);
     }
+    if config.multiline {
+        builder.multi_line(true);
+        builder.dotall(config.multiline_dotall);
+    }
 
     let matcher = builder
-        .build(pattern)
+        .build_many(patterns)
         .map_err(|err| SearchError::InvalidPattern(err.to_string()))?;
-    Ok(EngineMatcher::Pcre2(matcher))
+    Ok(InnerMatcher::Pcre2(matcher))
+}
+
+/// The matcher used for searching, plus what is needed to attribute
+/// submatches to their pattern when several patterns were given.
+///
+/// Each search builds its own, carrying the [`Interrupt`] of that run. Once
+/// the run is interrupted the matcher stops finding anything, which ends the
+/// file being searched.
+#[derive(Clone)]
+pub(crate) struct EngineMatcher {
+    inner: InnerMatcher,
+    attribution: Option<Arc<Attribution>>,
+    group_names: Vec<Option<String>>,
+    interrupt: Interrupt,
+}
+
+impl EngineMatcher {
+    pub(crate) fn interrupt(&self) -> &Interrupt {
+        &self.interrupt
+    }
+
+    /// Name of the capture group at `index`, if it is a named group.
+    pub(crate) fn group_name(&self, index: usize) -> Option<&str> {
+        self.group_names.get(index)?.as_deref()
+    }
+
+    /// Return the index of the pattern that produced the submatch `mat`
+    /// found in `haystack`.
+    pub(crate) fn pattern_index(&self, haystack: &[u8], mat: Match) -> usize {
+        self.attribution
+            .as_ref()
+            .map_or(0, |attribution| attribution.pattern_index(haystack, mat))
+    }
+}
+
+/// Attributes submatches to one of several patterns.
+///
+/// The combined matcher prefers earlier patterns, so an anchored search of a
+/// leftmost-first set of the same patterns at the start of a submatch usually
+/// finds the pattern that produced it in a single pass. When that search
+/// disagrees with the submatch, as the `word` and `line_regexp` wrappers or
+/// PCRE2-only syntax can make it, an overlapping set narrows the patterns down
+/// to candidates that are then checked with a matcher for each, built the
+/// first time it is needed. Patterns neither set can compile leave every
+/// pattern a candidate.
+struct Attribution {
+    patterns: Vec<String>,
+    config: Config,
+    first: OnceLock<Option<meta::Regex>>,
+    all: OnceLock<Option<meta::Regex>>,
+    each: Vec<OnceLock<Option<InnerMatcher>>>,
+}
+
+impl Attribution {
+    fn new(patterns: Vec<String>, config: &Config) -> Self {
+        Self {
+            each: patterns.iter().map(|_| OnceLock::new()).collect(),
+            patterns,
+            config: Config {
+                compiled: None,
+                ..config.clone()
+            },
+            first: OnceLock::new(),
+            all: OnceLock::new(),
+        }
+    }
+
+    fn pattern_index(&self, haystack: &[u8], mat: Match) -> usize {
+        if let Some(first) = self
+            .first
+            .get_or_init(|| self.build_set(MatchKind::LeftmostFirst))
+        {
+            let input = Input::new(haystack)
+                .range(mat.start()..)
+                .anchored(Anchored::Yes);
+            if let Some(found) = first.search(&input)
+                && found.range() == (mat.start()..mat.end())
+            {
+                return found.pattern().as_usize();
+            }
+        }
+
+        let candidates = self.candidates(haystack, mat);
+        let mut first_start = None;
+        for &index in &candidates {
+            let Some(matcher) = self.matcher(index) else {
+                continue;
+            };
+            let Ok(Some(found)) = matcher.find_at(haystack, mat.start()) else {
+                continue;
+            };
+            if found == mat {
+                return index;
+            }
+            if found.start() == mat.start() && first_start.is_none() {
+                first_start = Some(index);
+            }
+        }
+        first_start.unwrap_or(0)
+    }
+
+    /// The patterns matching anywhere within `mat`'s span from its start,
+    /// in pattern order.
+    fn candidates(&self, haystack: &[u8], mat: Match) -> Vec<usize> {
+        let Some(all) = self.all.get_or_init(|| self.build_set(MatchKind::All)) else {
+            return (0..self.patterns.len()).collect();
+        };
+        let input = Input::new(haystack)
+            .range(mat.start()..mat.end())
+            .anchored(Anchored::Yes);
+        let mut found = PatternSet::new(all.pattern_len());
+        all.which_overlapping_matches(&input, &mut found);
+        found.iter().map(|id| id.as_usize()).collect()
+    }
+
+    /// Build a set of all patterns, deciding case sensitivity the way the
+    /// combined matcher does.
+    fn build_set(&self, kind: MatchKind) -> Option<meta::Regex> {
+        let patterns: Vec<String> = if self.config.fixed_strings {
+            self.patterns
+                .iter()
+                .map(|p| regex_syntax::escape(p))
+                .collect()
+        } else {
+            self.patterns.clone()
+        };
+        let syntax = syntax::Config::new()
+            .case_insensitive(case_insensitive(&patterns, self.config.case_mode))
+            .multi_line(true)
+            .dot_matches_new_line(self.config.multiline_dotall)
+            .utf8(false);
+        meta::Builder::new()
+            .configure(
+                meta::Config::new()
+                    .match_kind(kind)
+                    .utf8_empty(false)
+                    // Every search is anchored, so a prefilter never helps and
+                    // extracting one from many patterns is slow.
+                    .auto_prefilter(false),
+            )
+            .syntax(syntax)
+            .build_many(&patterns)
+            .ok()
+    }
+
+    fn matcher(&self, index: usize) -> Option<&InnerMatcher> {
+        self.each[index]
+            .get_or_init(|| {
+                build_inner(std::slice::from_ref(&self.patterns[index]), &self.config).ok()
+            })
+            .as_ref()
+    }
+}
+
+/// Whether `patterns` are matched case insensitively. Smart case applies when
+/// the patterns contain a literal and none of their literals is uppercase.
+fn case_insensitive(patterns: &[String], mode: CaseMode) -> bool {
+    match mode {
+        CaseMode::Sensitive => false,
+        CaseMode::Insensitive => true,
+        CaseMode::Smart => {
+            let joined = patterns
+                .iter()
+                .map(|pattern| format!("(?:{pattern})"))
+                .collect::<Vec<_>>()
+                .join("|");
+            let Ok(parsed) = ast::parse::Parser::new().parse(&joined) else {
+                return false;
+            };
+            let Ok(literals) = ast::visit(&parsed, Literals::default());
+            literals.any && !literals.uppercase
+        }
+    }
+}
+
+/// Records whether a pattern has any literal characters, and any uppercase
+/// ones.
+#[derive(Default)]
+struct Literals {
+    any: bool,
+    uppercase: bool,
+}
+
+impl Literals {
+    fn add(&mut self, literal: &ast::Literal) {
+        self.any = true;
+        self.uppercase |= literal.c.is_uppercase();
+    }
 }
 
-pub(crate) enum EngineMatcher {
+impl ast::Visitor for Literals {
+    type Output = Self;
+    type Err = Infallible;
+
+    fn finish(self) -> Result<Self, Infallible> {
+        Ok(self)
+    }
+
+    fn visit_pre(&mut self, ast: &ast::Ast) -> Result<(), Infallible> {
+        if let ast::Ast::Literal(literal) = ast {
+            self.add(literal);
+        }
+        Ok(())
+    }
+
+    fn visit_class_set_item_pre(&mut self, item: &ast::ClassSetItem) -> Result<(), Infallible> {
+        match item {
+            ast::ClassSetItem::Literal(literal) => self.add(literal),
+            ast::ClassSetItem::Range(range) => {
+                self.add(&range.start);
+                self.add(&range.end);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Matcher for EngineMatcher {
+    type Captures = EngineCaptures;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, Self::Error> {
+        if self.interrupt.is_set() {
+            return Ok(None);
+        }
+        self.inner.find_at(haystack, at)
+    }
+
+    fn new_captures(&self) -> Result<Self::Captures, Self::Error> {
+        self.inner.new_captures()
+    }
+
+    fn captures_at(
+        &self,
+        haystack: &[u8],
+        at: usize,
+        caps: &mut Self::Captures,
+    ) -> Result<bool, Self::Error> {
+        if self.interrupt.is_set() {
+            return Ok(false);
+        }
+        self.inner.captures_at(haystack, at, caps)
+    }
+
+    fn capture_count(&self) -> usize {
+        self.inner.capture_count()
+    }
+
+    fn capture_index(&self, name: &str) -> Option<usize> {
+        self.inner.capture_index(name)
+    }
+
+    fn find(&self, haystack: &[u8]) -> Result<Option<Match>, Self::Error> {
+        if self.interrupt.is_set() {
+            return Ok(None);
+        }
+        self.inner.find(haystack)
+    }
+
+    fn shortest_match(&self, haystack: &[u8]) -> Result<Option<usize>, Self::Error> {
+        if self.interrupt.is_set() {
+            return Ok(None);
+        }
+        self.inner.shortest_match(haystack)
+    }
+}
+
+#[derive(Clone)]
+enum InnerMatcher {
     Regex(RegexMatcher),
     #[cfg(feature = "pcre2")]
     Pcre2(Pcre2Matcher),
 }
 
-impl Matcher for EngineMatcher {
-    type Captures = NoCaptures;
+pub(crate) enum EngineCaptures {
+    Regex(RegexCaptures),
+    #[cfg(feature = "pcre2")]
+    Pcre2(Pcre2Captures),
+}
+
+impl Captures for EngineCaptures {
+    fn len(&self) -> usize {
+        match self {
+            EngineCaptures::Regex(caps) => caps.len(),
+            #[cfg(feature = "pcre2")]
+            EngineCaptures::Pcre2(caps) => caps.len(),
+        }
+    }
+
+    fn get(&self, i: usize) -> Option<Match> {
+        match self {
+            EngineCaptures::Regex(caps) => caps.get(i),
+            #[cfg(feature = "pcre2")]
+            EngineCaptures::Pcre2(caps) => caps.get(i),
+        }
+    }
+}
+
+impl Matcher for InnerMatcher {
+    type Captures = EngineCaptures;
     type Error = Box<dyn std::error::Error + Send + Sync>;
 
     fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, Self::Error> {
         match self {
-            EngineMatcher::Regex(matcher) => matcher
+            InnerMatcher::Regex(matcher) => matcher
                 .find_at(haystack, at)
                 .map_err(|err| Box::new(err) as _),
             #[cfg(feature = "pcre2")]
-            EngineMatcher::Pcre2(matcher) => matcher
+            InnerMatcher::Pcre2(matcher) => matcher
                 .find_at(haystack, at)
                 .map_err(|err| Box::new(err) as _),
         }
     }
 
     fn new_captures(&self) -> Result<Self::Captures, Self::Error> {
-        Ok(NoCaptures::new())
+        match self {
+            InnerMatcher::Regex(matcher) => matcher
+                .new_captures()
+                .map(EngineCaptures::Regex)
+                .map_err(|err| Box::new(err) as _),
+            #[cfg(feature = "pcre2")]
+            InnerMatcher::Pcre2(matcher) => matcher
+                .new_captures()
+                .map(EngineCaptures::Pcre2)
+                .map_err(|err| Box::new(err) as _),
+        }
+    }
+
+    fn captures_at(
+        &self,
+        haystack: &[u8],
+        at: usize,
+        caps: &mut Self::Captures,
+    ) -> Result<bool, Self::Error> {
+        match (self, caps) {
+            (InnerMatcher::Regex(matcher), EngineCaptures::Regex(caps)) => matcher
+                .captures_at(haystack, at, caps)
+                .map_err(|err| Box::new(err) as _),
+            #[cfg(feature = "pcre2")]
+            (InnerMatcher::Pcre2(matcher), EngineCaptures::Pcre2(caps)) => matcher
+                .captures_at(haystack, at, caps)
+                .map_err(|err| Box::new(err) as _),
+            #[cfg(feature = "pcre2")]
+            _ => Err("captures were created by a different matcher".into()),
+        }
     }
 
     fn capture_count(&self) -> usize {
-        0
+        match self {
+            InnerMatcher::Regex(matcher) => matcher.capture_count(),
+            #[cfg(feature = "pcre2")]
+            InnerMatcher::Pcre2(matcher) => matcher.capture_count(),
+        }
     }
 
-    fn capture_index(&self, _name: &str) -> Option<usize> {
-        None
+    fn capture_index(&self, name: &str) -> Option<usize> {
+        match self {
+            InnerMatcher::Regex(matcher) => matcher.capture_index(name),
+            #[cfg(feature = "pcre2")]
+            InnerMatcher::Pcre2(matcher) => matcher.capture_index(name),
+        }
     }
 
     fn find(&self, haystack: &[u8]) -> Result<Option<Match>, Self::Error> {
         match self {
-            EngineMatcher::Regex(matcher) => {
+            InnerMatcher::Regex(matcher) => {
                 matcher.find(haystack).map_err(|err| Box::new(err) as _)
             }
             #[cfg(feature = "pcre2")]
-            EngineMatcher::Pcre2(matcher) => {
+            InnerMatcher::Pcre2(matcher) => {
                 matcher.find(haystack).map_err(|err| Box::new(err) as _)
             }
         }
@@ -121,11 +548,11 @@ impl Matcher for EngineMatcher {
 
     fn shortest_match(&self, haystack: &[u8]) -> Result<Option<usize>, Self::Error> {
         match self {
-            EngineMatcher::Regex(matcher) => matcher
+            InnerMatcher::Regex(matcher) => matcher
                 .shortest_match(haystack)
                 .map_err(|err| Box::new(err) as _),
             #[cfg(feature = "pcre2")]
-            EngineMatcher::Pcre2(matcher) => matcher
+            InnerMatcher::Pcre2(matcher) => matcher
                 .shortest_match(haystack)
                 .map_err(|err| Box::new(err) as _),
         }
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use grep_matcher::{Captures, Matcher};
+
+use crate::matcher::EngineMatcher;
+use crate::types::SubMatch;
+
+/// How submatches are rewritten when a replacement is configured.
+#[derive(Clone)]
+pub(crate) enum Replacement {
+    /// A template where `$1`, `${1}`, `$name` and `${name}` refer to capture
+    /// groups and `$$` is a literal `$`.
+    Template(String),
+    /// A closure computing the replacement for each submatch.
+    With(Arc<dyn Fn(&SubMatch) -> String + Send + Sync>),
+}
+
+impl fmt::Debug for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Template(template) => f.debug_tuple("Template").field(template).finish(),
+            Self::With(_) => f.debug_tuple("With").field(&"<closure>").finish(),
+        }
+    }
+}
+
+impl Replacement {
+    /// Rewrite every submatch in `bytes`, leaving the rest untouched.
+    pub(crate) fn apply(
+        &self,
+        matcher: &EngineMatcher,
+        bytes: &[u8],
+        submatches: &[SubMatch],
+    ) -> io::Result<Vec<u8>> {
+        let replaced = self.each(matcher, bytes, submatches)?;
+        let mut dst = Vec::with_capacity(bytes.len());
+        let mut last = 0;
+        for (submatch, replaced) in submatches.iter().zip(replaced) {
+            dst.extend_from_slice(&bytes[last..submatch.start]);
+            dst.extend_from_slice(&replaced);
+            last = submatch.end;
+        }
+        dst.extend_from_slice(&bytes[last..]);
+        Ok(dst)
+    }
+
+    /// Compute the replacement for each submatch in `bytes`, in order.
+    pub(crate) fn each(
+        &self,
+        matcher: &EngineMatcher,
+        bytes: &[u8],
+        submatches: &[SubMatch],
+    ) -> io::Result<Vec<Vec<u8>>> {
+        match self {
+            Self::Template(template) => {
+                let mut replaced = Vec::with_capacity(submatches.len());
+                let mut caps = matcher
+                    .new_captures()
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                matcher
+                    .captures_iter(bytes, &mut caps, |caps| {
+                        let mut dst = Vec::new();
+                        caps.interpolate(
+                            |name| matcher.capture_index(name),
+                            bytes,
+                            template.as_bytes(),
+                            &mut dst,
+                        );
+                        replaced.push(dst);
+                        true
+                    })
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                Ok(replaced)
+            }
+            Self::With(replace) => Ok(submatches
+                .iter()
+                .map(|submatch| replace(submatch).into_bytes())
+                .collect()),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::error::FileError;
+
+/// A shared handle that collects what happened during a search.
+///
+/// Attach it with [`SearchBuilder::report`](crate::SearchBuilder::report)
+/// and inspect it once the search returns. Clones share the same state, so
+/// the handle can be kept while the builder is consumed. Statistics add up
+/// across every search the report is attached to.
+///
+/// ```rust
+/// use ripgrep_api::{SearchBuilder, SearchReport};
+///
+/// let report = SearchReport::new();
+/// let total = SearchBuilder::new("alpha")
+///     .path(".")
+///     .report(&report)
+///     .count()?;
+///
+/// let stats = report.stats();
+/// println!("{total} matches in {} files", stats.files_searched);
+/// for error in report.take_errors() {
+///     eprintln!("skipped: {error}");
+/// }
+/// # Ok::<(), ripgrep_api::SearchError>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SearchReport {
+    inner: Arc<ReportState>,
+}
+
+#[derive(Debug, Default)]
+struct ReportState {
+    errors: Mutex<Vec<FileError>>,
+    binary_paths: Mutex<Vec<(PathBuf, u64)>>,
+    files_walked: AtomicU64,
+    files_searched: AtomicU64,
+    files_with_matches: AtomicU64,
+    binary_files: AtomicU64,
+    skipped_binary: AtomicU64,
+    skipped_filesize: AtomicU64,
+    bytes_searched: AtomicU64,
+    matched_lines: AtomicU64,
+    matches: AtomicU64,
+    elapsed_nanos: AtomicU64,
+}
+
+/// Statistics gathered by a [`SearchReport`], like `rg --stats`.
+///
+/// Files skipped by ignore files, hidden-file rules, globs or file types are
+/// not counted, as in `rg --stats`: the walker drops them before they are
+/// seen, and counting them would mean listing every directory a second time.
+/// They are simply absent from `files_walked`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Files produced by the walk, before size and binary checks.
+    pub files_walked: u64,
+    /// Files handed to the searcher.
+    pub files_searched: u64,
+    /// Searched files with at least one match.
+    pub files_with_matches: u64,
+    /// Files where binary data was found, whatever the binary mode.
+    pub binary_files: u64,
+    /// Files where searching stopped because binary data was found.
+    pub skipped_binary: u64,
+    /// Files larger than [`max_filesize`](crate::SearchBuilder::max_filesize).
+    pub skipped_filesize: u64,
+    /// Bytes read by the searcher.
+    pub bytes_searched: u64,
+    /// Lines that matched, counting every line of a multiline match.
+    pub matched_lines: u64,
+    /// Individual matches; equal to `matched_lines` for inverted searches.
+    pub matches: u64,
+    /// Wall-clock time spent inside searches the report was attached to.
+    pub elapsed: Duration,
+}
+
+/// Per-file numbers collected while a single file is searched.
+#[derive(Debug, Default)]
+pub(crate) struct FileStats {
+    pub(crate) bytes_searched: u64,
+    pub(crate) matched_lines: u64,
+    pub(crate) matches: u64,
+    /// Where binary data was found, if anywhere.
+    pub(crate) binary_offset: Option<u64>,
+    /// Whether finding binary data stops the search of the file.
+    pub(crate) binary_skipped: bool,
+}
+
+impl SearchReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of per-file errors recorded so far.
+    pub fn error_count(&self) -> usize {
+        self.errors().len()
+    }
+
+    /// Remove and return the per-file errors recorded so far.
+    pub fn take_errors(&self) -> Vec<FileError> {
+        std::mem::take(&mut *self.errors())
+    }
+
+    /// Files where binary data was found, with the byte offset of the first
+    /// NUL byte.
+    pub fn binary_files(&self) -> Vec<(PathBuf, u64)> {
+        lock(&self.inner.binary_paths).clone()
+    }
+
+    /// A snapshot of the statistics gathered so far.
+    pub fn stats(&self) -> SearchStats {
+        let state = &self.inner;
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        SearchStats {
+            files_walked: load(&state.files_walked),
+            files_searched: load(&state.files_searched),
+            files_with_matches: load(&state.files_with_matches),
+            binary_files: load(&state.binary_files),
+            skipped_binary: load(&state.skipped_binary),
+            skipped_filesize: load(&state.skipped_filesize),
+            bytes_searched: load(&state.bytes_searched),
+            matched_lines: load(&state.matched_lines),
+            matches: load(&state.matches),
+            elapsed: Duration::from_nanos(load(&state.elapsed_nanos)),
+        }
+    }
+
+    pub(crate) fn record_error(&self, error: FileError) {
+        self.errors().push(error);
+    }
+
+    pub(crate) fn record_file_walked(&self) {
+        self.inner.files_walked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_skipped_filesize(&self) {
+        self.inner.skipped_filesize.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_file(&self, path: &Path, file: &FileStats) {
+        let state = &self.inner;
+        state.files_searched.fetch_add(1, Ordering::Relaxed);
+        if file.matched_lines > 0 {
+            state.files_with_matches.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(offset) = file.binary_offset {
+            state.binary_files.fetch_add(1, Ordering::Relaxed);
+            if file.binary_skipped {
+                state.skipped_binary.fetch_add(1, Ordering::Relaxed);
+            }
+            lock(&state.binary_paths).push((path.to_path_buf(), offset));
+        }
+        state
+            .bytes_searched
+            .fetch_add(file.bytes_searched, Ordering::Relaxed);
+        state
+            .matched_lines
+            .fetch_add(file.matched_lines, Ordering::Relaxed);
+        state.matches.fetch_add(file.matches, Ordering::Relaxed);
+    }
+
+    /// Start timing a search; the elapsed time is added when the guard drops.
+    pub(crate) fn timer(&self) -> ReportTimer<'_> {
+        ReportTimer {
+            report: self,
+            start: Instant::now(),
+        }
+    }
+
+    fn errors(&self) -> MutexGuard<'_, Vec<FileError>> {
+        lock(&self.inner.errors)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub(crate) struct ReportTimer<'a> {
+    report: &'a SearchReport,
+    start: Instant,
+}
+
+impl Drop for ReportTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.report
+            .inner
+            .elapsed_nanos
+            .fetch_add(elapsed, Ordering::Relaxed);
+    }
+}
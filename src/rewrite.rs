@@ -0,0 +1,362 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use grep_searcher::{Searcher, Sink, SinkMatch};
+
+use crate::config::Config;
+use crate::engine;
+use crate::error::SearchError;
+use crate::matcher::{self, EngineMatcher};
+use crate::replace::Replacement;
+use crate::types::{FileRewrite, RewriteReport};
+
+/// Lines of unchanged context around each hunk of a dry-run diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// Apply the configured replacement to every matched file.
+///
+/// All new contents are staged next to their originals before any file is
+/// replaced, and files already replaced are restored if a later one fails.
+/// Unreadable files fail the rewrite before anything is written, as in
+/// strict mode.
+pub(crate) fn rewrite(config: &Config) -> Result<RewriteReport, SearchError> {
+    let Some(replacement) = &config.replacement else {
+        return Err(SearchError::InvalidReplacement(
+            "rewrite requires replace or replace_with".to_string(),
+        ));
+    };
+    // Edits are byte ranges into the file as stored, so never transcode.
+    // A file that cannot be read or searched fails the whole rewrite rather
+    // than being skipped, so an edit never lands partially.
+    let config = &Config {
+        encoding: Some("none".to_string()),
+        strict: true,
+        ..config.clone()
+    };
+    let matcher = matcher::build_matcher(config)?;
+    let walker = engine::build_walker(config)?;
+    let max_count = engine::effective_max_count(config.max_count, config.limit);
+    let mut remaining = config.limit;
+    let mut plans = Vec::new();
+
+    engine::walk_files_with(
+        config,
+        &walker,
+        matcher.interrupt(),
+        |searcher, path| plan_file(config, searcher, &matcher, replacement, max_count, path),
+        |_, plan: Option<FilePlan>| {
+            if let Some(mut plan) = plan {
+                if let Some(remaining) = remaining.as_mut() {
+                    plan.edits.truncate(*remaining);
+                    *remaining -= plan.edits.len();
+                }
+                if plan.edits.iter().any(|edit| edit.changed) {
+                    plans.push(plan);
+                }
+            }
+            remaining != Some(0)
+        },
+    )?;
+    plans.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if config.dry_run {
+        let files = plans
+            .iter()
+            .map(|plan| FileRewrite {
+                path: plan.path.clone(),
+                replacements: plan.replacements(),
+                diff: Some(plan.diff()),
+                backup: None,
+            })
+            .collect();
+        return Ok(RewriteReport {
+            files,
+            dry_run: true,
+        });
+    }
+
+    let files = commit(&plans, config.backup_suffix.as_deref())?;
+    Ok(RewriteReport {
+        files,
+        dry_run: false,
+    })
+}
+
+/// Search `path` and record how each matched line would be rewritten.
+fn plan_file(
+    config: &Config,
+    searcher: &mut Searcher,
+    matcher: &EngineMatcher,
+    replacement: &Replacement,
+    max_count: Option<usize>,
+    path: &Path,
+) -> Result<Option<FilePlan>, SearchError> {
+    let original = fs::read(path)?;
+    let mut sink = EditSink {
+        matcher,
+        replacement,
+        max_count,
+        edits: Vec::new(),
+    };
+    engine::tracked(config, matcher, path, &mut sink, |sink| {
+        searcher.search_slice(matcher, &original, sink)
+    })?;
+    if sink.edits.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(FilePlan {
+        path: path.to_path_buf(),
+        original,
+        edits: sink.edits,
+    }))
+}
+
+/// A replacement of the matched lines starting at `line` (zero based).
+struct Edit {
+    line: usize,
+    start: usize,
+    end: usize,
+    replaced: Vec<u8>,
+    replacements: usize,
+    changed: bool,
+}
+
+struct EditSink<'a> {
+    matcher: &'a EngineMatcher,
+    replacement: &'a Replacement,
+    max_count: Option<usize>,
+    edits: Vec<Edit>,
+}
+
+impl Sink for EditSink<'_> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        let bytes = mat.bytes();
+        let offset = mat.absolute_byte_offset();
+        let submatches = engine::find_submatches(self.matcher, bytes, offset)?;
+        let replaced = self.replacement.apply(self.matcher, bytes, &submatches)?;
+        let start = offset as usize;
+        let line = mat.line_number().unwrap_or(1).saturating_sub(1) as usize;
+
+        self.edits.push(Edit {
+            line,
+            start,
+            end: start + bytes.len(),
+            changed: replaced != bytes,
+            replaced,
+            replacements: submatches.len(),
+        });
+        Ok(self
+            .max_count
+            .is_none_or(|max_count| self.edits.len() < max_count))
+    }
+}
+
+struct FilePlan {
+    path: PathBuf,
+    original: Vec<u8>,
+    edits: Vec<Edit>,
+}
+
+impl FilePlan {
+    fn replacements(&self) -> usize {
+        self.edits.iter().map(|edit| edit.replacements).sum()
+    }
+
+    fn rewritten(&self) -> Vec<u8> {
+        let mut contents = Vec::with_capacity(self.original.len());
+        let mut last = 0;
+        for edit in &self.edits {
+            contents.extend_from_slice(&self.original[last..edit.start]);
+            contents.extend_from_slice(&edit.replaced);
+            last = edit.end;
+        }
+        contents.extend_from_slice(&self.original[last..]);
+        contents
+    }
+
+    /// Render the planned edits as a unified diff.
+    fn diff(&self) -> String {
+        let old_lines: Vec<&[u8]> = self.original.split_inclusive(|&b| b == b'\n').collect();
+        let edits: Vec<_> = self.edits.iter().filter(|edit| edit.changed).collect();
+        let mut out = format!(
+            "--- a/{}\n+++ b/{}\n",
+            self.path.display(),
+            self.path.display()
+        );
+        let mut delta: isize = 0;
+        let mut index = 0;
+
+        while index < edits.len() {
+            let mut group_end = index + 1;
+            while group_end < edits.len() {
+                let prev = edits[group_end - 1];
+                let prev_end = prev.line + line_count(&self.original[prev.start..prev.end]);
+                if edits[group_end].line > prev_end + 2 * DIFF_CONTEXT {
+                    break;
+                }
+                group_end += 1;
+            }
+            let group = &edits[index..group_end];
+            index = group_end;
+
+            let first = group[0];
+            let last = group[group.len() - 1];
+            let old_start = first.line.saturating_sub(DIFF_CONTEXT);
+            let last_end = last.line + line_count(&self.original[last.start..last.end]);
+            let old_end = (last_end + DIFF_CONTEXT).min(old_lines.len());
+
+            let mut body = String::new();
+            let mut old_len = 0;
+            let mut new_len = 0;
+            let mut line = old_start;
+            for edit in group {
+                while line < edit.line {
+                    push_diff_line(&mut body, ' ', old_lines[line]);
+                    old_len += 1;
+                    new_len += 1;
+                    line += 1;
+                }
+                let removed = &self.original[edit.start..edit.end];
+                for old in removed.split_inclusive(|&b| b == b'\n') {
+                    push_diff_line(&mut body, '-', old);
+                    old_len += 1;
+                }
+                for new in edit.replaced.split_inclusive(|&b| b == b'\n') {
+                    push_diff_line(&mut body, '+', new);
+                    new_len += 1;
+                }
+                line += line_count(removed);
+            }
+            while line < old_end {
+                push_diff_line(&mut body, ' ', old_lines[line]);
+                old_len += 1;
+                new_len += 1;
+                line += 1;
+            }
+
+            let new_start = (old_start as isize + delta) as usize;
+            delta += new_len as isize - old_len as isize;
+            out.push_str(&format!(
+                "@@ -{},{} +{},{} @@\n",
+                hunk_start(old_start, old_len),
+                old_len,
+                hunk_start(new_start, new_len),
+                new_len
+            ));
+            out.push_str(&body);
+        }
+        out
+    }
+}
+
+fn line_count(bytes: &[u8]) -> usize {
+    bytes.split_inclusive(|&b| b == b'\n').count()
+}
+
+/// Unified diffs number an empty range by the line before it.
+fn hunk_start(start: usize, len: usize) -> usize {
+    if len == 0 { start } else { start + 1 }
+}
+
+fn push_diff_line(out: &mut String, marker: char, line: &[u8]) {
+    out.push(marker);
+    out.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// Stage every rewritten file, then swap them into place, rolling back on
+/// the first failure.
+fn commit(
+    plans: &[FilePlan],
+    backup_suffix: Option<&str>,
+) -> Result<Vec<FileRewrite>, SearchError> {
+    let mut staged = Vec::with_capacity(plans.len());
+    for plan in plans {
+        match stage(&plan.path, &plan.rewritten()) {
+            Ok(temp) => staged.push(temp),
+            Err(err) => {
+                for temp in &staged {
+                    let _ = fs::remove_file(temp);
+                }
+                return Err(err.into());
+            }
+        }
+    }
+
+    let mut files = Vec::with_capacity(plans.len());
+    for (index, (plan, temp)) in plans.iter().zip(&staged).enumerate() {
+        let backup = backup_suffix.map(|suffix| {
+            let mut name = plan.path.clone().into_os_string();
+            name.push(suffix);
+            PathBuf::from(name)
+        });
+        let result = backup
+            .as_ref()
+            .map_or(Ok(0), |backup| fs::copy(&plan.path, backup))
+            .and_then(|_| fs::rename(temp, &plan.path));
+
+        if let Err(err) = result {
+            rollback(&plans[..index], &files);
+            if let Some(backup) = &backup {
+                let _ = fs::remove_file(backup);
+            }
+            for temp in &staged[index..] {
+                let _ = fs::remove_file(temp);
+            }
+            return Err(err.into());
+        }
+
+        files.push(FileRewrite {
+            path: plan.path.clone(),
+            replacements: plan.replacements(),
+            diff: None,
+            backup,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Restore the original contents of files that were already replaced.
+fn rollback(plans: &[FilePlan], files: &[FileRewrite]) {
+    for (plan, file) in plans.iter().zip(files) {
+        if let Ok(temp) = stage(&plan.path, &plan.original) {
+            let _ = fs::rename(temp, &plan.path);
+        }
+        if let Some(backup) = &file.backup {
+            let _ = fs::remove_file(backup);
+        }
+    }
+}
+
+/// Write `contents` to a temporary file beside `path` with the same
+/// permissions, returning the temporary path.
+fn stage(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    let permissions = fs::metadata(path)?.permissions();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(
+        ".{file_name}.{}.ripgrep-api.tmp",
+        std::process::id()
+    ));
+
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()?;
+            fs::set_permissions(&temp, permissions)
+        });
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    Ok(temp)
+}
@@ -1,22 +1,108 @@
-use crate::{config::Config, engine, error::SearchError, types::Match};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
+use crate::{
+    config::Config,
+    engine::{self, Attach, Limit},
+    error::SearchError,
+    input::Inputs,
+    matcher,
+    types::Match,
+};
+
+/// Number of matches buffered ahead of the consumer before the background
+/// walker blocks.
+pub(crate) const BUFFER_SIZE: usize = 256;
+
+/// A lazy iterator over search results.
+///
+/// The walk runs on a background thread and feeds a bounded buffer, so the
+/// first matches are available as soon as they are found. Dropping the
+/// `Search` stops the walk.
+///
+/// Unreadable files and directories are skipped and recorded in any
+/// attached [`SearchReport`](crate::SearchReport). In
+/// [`strict`](crate::SearchBuilder::strict) mode they end the iteration
+/// instead, and the error can be retrieved with
+/// [`take_error`](Self::take_error).
+///
+/// ```rust
+/// use ripgrep_api::SearchBuilder;
+///
+/// let mut search = SearchBuilder::new("alpha").path(".").build()?;
+/// for mat in &mut search {
+///     println!("{}:{}", mat.path.display(), mat.line.unwrap_or(0));
+/// }
+/// if let Some(err) = search.take_error() {
+///     eprintln!("search stopped early: {err}");
+/// }
+/// # Ok::<(), ripgrep_api::SearchError>(())
+/// ```
 pub struct Search {
-    inner: std::vec::IntoIter<Match>,
+    receiver: Receiver<Result<Match, SearchError>>,
+    error: Option<SearchError>,
 }
 
 impl Search {
     pub(crate) fn from_config(config: Config) -> Result<Self, SearchError> {
-        let results = engine::search(&config)?;
+        let (sender, receiver) = mpsc::sync_channel(BUFFER_SIZE);
+        let job = background_walk(config, move |result| sender.send(result).is_ok())?;
+
+        thread::Builder::new()
+            .name("ripgrep-api-search".to_string())
+            .spawn(job)?;
+
         Ok(Self {
-            inner: results.into_iter(),
+            receiver,
+            error: None,
         })
     }
+
+    /// Take the error that ended the search early, if any.
+    pub fn take_error(&mut self) -> Option<SearchError> {
+        self.error.take()
+    }
+}
+
+/// Build the matcher, walker and inputs for `config` and return the job that
+/// walks them on a background thread, handing each result to `send`. Invalid
+/// options are reported here rather than by the job. The job stops once
+/// `send` returns `false`; an error ending the walk is sent last.
+pub(crate) fn background_walk<F>(
+    config: Config,
+    mut send: F,
+) -> Result<impl FnOnce() + Send + 'static, SearchError>
+where
+    F: FnMut(Result<Match, SearchError>) -> bool + Send + 'static,
+{
+    let matcher = matcher::build_matcher(&config)?;
+    let walker = engine::build_walker(&config)?;
+    let inputs = Inputs::new(&config)?;
+    engine::searcher_builder(&config)?;
+
+    Ok(move || {
+        let result = {
+            let mut emit = Attach::new(|mat| send(Ok(mat)));
+            let mut limit = Limit::new(config.limit, &mut emit);
+            engine::search_walk(&config, &matcher, &walker, &inputs, &mut limit)
+        };
+        if let Err(err) = result {
+            send(Err(err));
+        }
+    })
 }
 
 impl Iterator for Search {
     type Item = Match;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        match self.receiver.recv() {
+            Ok(Ok(mat)) => Some(mat),
+            Ok(Err(err)) => {
+                self.error = Some(err);
+                None
+            }
+            Err(_) => None,
+        }
     }
 }
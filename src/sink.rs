@@ -1,3 +1,6 @@
+use std::path::Path;
+
+use crate::error::FileError;
 use crate::types::{ContextLine, Match};
 
 pub trait MatchSink {
@@ -7,5 +10,23 @@ pub trait MatchSink {
         true
     }
 
+    /// Called between non-contiguous groups of context lines within a file,
+    /// where ripgrep prints a `--` separator.
+    fn context_break(&mut self) -> bool {
+        true
+    }
+
+    /// Called when binary data is found in `path` at byte `offset`.
+    ///
+    /// With [`BinaryMode::Skip`](crate::BinaryMode::Skip) the rest of the
+    /// file is skipped; otherwise returning `false` stops the file here.
+    fn binary(&mut self, _path: &Path, _offset: u64) -> bool {
+        true
+    }
+
+    /// Called when a file or directory could not be searched. The search
+    /// keeps going unless it is [`strict`](crate::SearchBuilder::strict).
+    fn error(&mut self, _error: &FileError) {}
+
     fn finish(&mut self) {}
 }
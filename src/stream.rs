@@ -0,0 +1,57 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{self, Receiver};
+
+use crate::{
+    config::Config,
+    error::SearchError,
+    search::{self, BUFFER_SIZE},
+    types::Match,
+};
+
+/// An asynchronous [`Stream`] of search results, available with the `tokio`
+/// feature.
+///
+/// The walk runs on Tokio's blocking thread pool and feeds a bounded buffer,
+/// so a slow consumer holds the walk back instead of letting results pile up.
+/// Dropping the stream stops the walk.
+///
+/// Unlike [`Search`](crate::Search), errors are yielded as items. Unreadable
+/// files are skipped and recorded in any attached
+/// [`SearchReport`](crate::SearchReport); in
+/// [`strict`](crate::SearchBuilder::strict) mode the error ends the stream.
+///
+/// Creating the stream outside a Tokio runtime fails with a
+/// [`SearchError::Io`] of kind [`io::ErrorKind::Unsupported`].
+pub struct SearchStream {
+    receiver: Receiver<Result<Match, SearchError>>,
+}
+
+impl SearchStream {
+    pub(crate) fn from_config(config: Config) -> Result<Self, SearchError> {
+        let handle = Handle::try_current().map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("search_stream must be called within a Tokio runtime: {err}"),
+            )
+        })?;
+        let (sender, receiver) = mpsc::channel(BUFFER_SIZE);
+        let job =
+            search::background_walk(config, move |result| sender.blocking_send(result).is_ok())?;
+        handle.spawn_blocking(job);
+
+        Ok(Self { receiver })
+    }
+}
+
+impl Stream for SearchStream {
+    type Item = Result<Match, SearchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
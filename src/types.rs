@@ -19,10 +19,19 @@ use std::path::PathBuf;
 pub struct Match {
     pub path: PathBuf,
     pub line: Option<u64>,
+    /// Last line covered by the match. Equal to `line` unless a multiline
+    /// match spans several lines.
+    pub end_line: Option<u64>,
     pub column: Option<usize>,
+    /// Absolute byte offset of the start of `bytes` in the searched input,
+    /// like `rg -b`.
+    pub byte_offset: u64,
     pub bytes: Vec<u8>,
     pub submatches: Vec<SubMatch>,
     pub line_text: String,
+    /// `line_text` with every submatch rewritten, when a replacement was
+    /// configured with `replace` or `replace_with`.
+    pub replacement: Option<String>,
     pub context: Vec<ContextLine>,
 }
 
@@ -30,6 +39,41 @@ pub struct Match {
 pub struct SubMatch {
     pub start: usize,
     pub end: usize,
+    /// Absolute byte offset of the submatch in the searched input.
+    pub byte_offset: u64,
+    /// Index of the pattern that produced this submatch, in the order the
+    /// patterns were added to the builder.
+    pub pattern: usize,
+    /// Capture groups indexed by group number, with the whole submatch at
+    /// index 0. Groups that did not participate are `None`. Empty when the
+    /// pattern has no capture groups.
+    pub captures: Vec<Option<Capture>>,
+}
+
+impl SubMatch {
+    /// Return the capture group with the given number.
+    pub fn group(&self, index: usize) -> Option<&Capture> {
+        self.captures.get(index)?.as_ref()
+    }
+
+    /// Return the named capture group `name`.
+    pub fn name(&self, name: &str) -> Option<&Capture> {
+        self.captures
+            .iter()
+            .flatten()
+            .find(|capture| capture.name.as_deref() == Some(name))
+    }
+}
+
+/// A capture group within a [`SubMatch`].
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub index: usize,
+    pub name: Option<String>,
+    /// Byte offset of the group within [`Match::bytes`].
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
 }
 
 #[derive(Debug, Clone)]
@@ -37,6 +81,8 @@ pub struct ContextLine {
     pub path: PathBuf,
     pub kind: ContextKind,
     pub line: Option<u64>,
+    /// Absolute byte offset of the start of the line in the searched input.
+    pub byte_offset: u64,
     pub bytes: Vec<u8>,
     pub line_text: String,
 }
@@ -47,3 +93,102 @@ pub enum ContextKind {
     After,
     Other,
 }
+
+/// Every result from one file, grouped into hunks of contiguous lines.
+///
+/// Returned by [`SearchBuilder::file_results`](crate::SearchBuilder::file_results).
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FileResult {
+    /// Iterate over the matches in every hunk, in file order.
+    pub fn matches(&self) -> impl Iterator<Item = &Match> {
+        self.hunks.iter().flat_map(|hunk| {
+            hunk.lines.iter().filter_map(|line| match line {
+                HunkLine::Match(mat) => Some(mat),
+                HunkLine::Context(_) => None,
+            })
+        })
+    }
+}
+
+/// A run of contiguous matched and context lines, like a diff hunk. Lines
+/// shared by the context of nearby matches appear once.
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// First line of the hunk, when line numbers are enabled.
+    pub start_line: Option<u64>,
+    /// Last line of the hunk, when line numbers are enabled.
+    pub end_line: Option<u64>,
+    pub lines: Vec<HunkLine>,
+}
+
+/// A line within a [`Hunk`]. Matches carry no context of their own here.
+#[derive(Debug, Clone)]
+pub enum HunkLine {
+    Match(Match),
+    Context(ContextLine),
+}
+
+/// How files containing binary data are handled, for
+/// [`SearchBuilder::binary`](crate::SearchBuilder::binary). A file is
+/// considered binary when it contains a NUL byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BinaryMode {
+    /// Stop searching the file once a NUL byte is seen. The check runs on
+    /// each buffer read (64 KiB by default, the whole file when memory
+    /// mapped) before it is searched, so matches in the buffer holding the
+    /// NUL are dropped too and only matches in earlier buffers are reported.
+    /// A small binary file therefore reports nothing. This is ripgrep's
+    /// default.
+    #[default]
+    Skip,
+    /// Search the whole file, treating NUL bytes as line terminators, like
+    /// `rg --binary`.
+    Convert,
+    /// Search the file as if it were text, like `rg -a`.
+    Text,
+}
+
+/// What to sort results by, for [`SearchBuilder::sort_by`](crate::SearchBuilder::sort_by).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SortKey {
+    Path,
+    /// Last modification time.
+    Modified,
+    /// Last access time.
+    Accessed,
+    /// Creation time, where the platform records it.
+    Created,
+}
+
+/// Sort direction, like ripgrep's `--sort` and `--sortr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Order {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// The outcome of [`SearchBuilder::rewrite`](crate::SearchBuilder::rewrite).
+#[derive(Debug, Clone)]
+pub struct RewriteReport {
+    /// Every file that was (or, in a dry run, would be) changed, sorted by
+    /// path.
+    pub files: Vec<FileRewrite>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileRewrite {
+    pub path: PathBuf,
+    /// Number of submatches replaced in the file.
+    pub replacements: usize,
+    /// Unified diff of the change, present in a dry run.
+    pub diff: Option<String>,
+    /// Where the original contents were saved, when backups are enabled.
+    pub backup: Option<PathBuf>,
+}
@@ -1,7 +1,10 @@
 use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
-use ripgrep_api::{ContextKind, MatchSink, SearchBuilder};
+use ripgrep_api::{
+    BinaryMode, CancellationToken, ContextKind, FileError, HunkLine, MatchSink, Order, Partial,
+    SearchBuilder, SearchReport, SortKey,
+};
 
 fn fixture_root() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -136,6 +139,7 @@ fn context_lines_are_collected() {
 }
 
 #[test]
+#[allow(clippy::cmp_owned)]
 fn max_count_caps_matches_per_file() {
     let root = fixture_root();
     let results: Vec<_> = SearchBuilder::new("alpha")
@@ -159,6 +163,63 @@ fn count_returns_total_matches() {
     assert_eq!(total, 5);
 }
 
+#[test]
+fn count_per_file_reports_each_matching_file() {
+    let root = fixture_root();
+    let counts: BTreeSet<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .count_per_file()
+        .unwrap()
+        .into_iter()
+        .map(|(path, count)| (rel(&path, &root), count))
+        .collect();
+
+    assert_eq!(
+        counts,
+        BTreeSet::from([
+            (PathBuf::from("custom.foo"), 1),
+            (PathBuf::from("nested/deeper/deep.txt"), 1),
+            (PathBuf::from("nested/inner.rs"), 1),
+            (PathBuf::from("root.txt"), 2),
+        ])
+    );
+}
+
+#[test]
+fn count_matches_counts_every_occurrence() {
+    let haystack = b"foo foo\nbar\nfoo\nfoo foo foo\n";
+    let dir = scratch_dir("count-matches");
+    std::fs::write(dir.join("a.txt"), haystack).unwrap();
+
+    let lines = SearchBuilder::new("foo").path(&dir).count().unwrap();
+    assert_eq!(lines, 3);
+
+    let matches = SearchBuilder::new("foo")
+        .path(&dir)
+        .count_matches()
+        .count()
+        .unwrap();
+    assert_eq!(matches, 6);
+
+    let capped = SearchBuilder::new("foo")
+        .path(&dir)
+        .count_matches()
+        .max_count(2)
+        .count_per_file()
+        .unwrap();
+    assert_eq!(capped, vec![(dir.join("a.txt"), 3)]);
+
+    let limited = SearchBuilder::new("foo")
+        .path(&dir)
+        .count_matches()
+        .limit(4)
+        .count()
+        .unwrap();
+    assert_eq!(limited, 4);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[test]
 fn files_with_matches_returns_unique_paths() {
     let root = fixture_root();
@@ -180,6 +241,23 @@ fn files_with_matches_returns_unique_paths() {
     );
 }
 
+#[test]
+fn files_without_match_returns_the_complement() {
+    let root = fixture_root();
+    let files = SearchBuilder::new("alpha")
+        .path(&root)
+        .glob("*.txt")
+        .files_without_match()
+        .unwrap();
+
+    let rel_files: BTreeSet<_> = files.iter().map(|path| rel(path, &root)).collect();
+
+    assert_eq!(
+        rel_files,
+        BTreeSet::from([PathBuf::from("context.txt"), PathBuf::from("large.txt")])
+    );
+}
+
 #[test]
 fn type_definitions_can_be_added() {
     let root = fixture_root();
@@ -418,3 +496,1445 @@ fn walk_files_returns_only_files() {
     assert!(!rel_files.contains(Path::new("nested/deeper")));
     assert!(rel_files.contains(Path::new("nested/deeper/deep.txt")));
 }
+
+#[test]
+fn search_can_be_dropped_before_exhausted() {
+    let root = fixture_root();
+    let mut search = SearchBuilder::new("alpha").path(&root).build().unwrap();
+
+    let first = search.next().unwrap();
+    assert!(first.line_text.to_lowercase().contains("alpha"));
+    drop(search);
+}
+
+#[test]
+fn strict_search_reports_walk_errors_after_iteration() {
+    let root = fixture_root();
+    let mut search = SearchBuilder::new("alpha")
+        .path(root.join("does-not-exist"))
+        .strict()
+        .build()
+        .unwrap();
+
+    assert!(search.next().is_none());
+    assert!(search.take_error().is_some());
+}
+
+#[test]
+fn parallel_search_matches_sequential_results() {
+    let root = fixture_root();
+    let sequential: BTreeSet<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .build()
+        .unwrap()
+        .map(|m| (rel(&m.path, &root), m.line))
+        .collect();
+    let parallel: BTreeSet<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .threads(4)
+        .build()
+        .unwrap()
+        .map(|m| (rel(&m.path, &root), m.line))
+        .collect();
+
+    assert_eq!(parallel, sequential);
+    assert_eq!(
+        SearchBuilder::new("alpha")
+            .path(&root)
+            .threads(4)
+            .count()
+            .unwrap(),
+        5
+    );
+    assert_eq!(
+        SearchBuilder::new("alpha")
+            .path(&root)
+            .threads(4)
+            .files_with_matches()
+            .unwrap()
+            .len(),
+        4
+    );
+}
+
+#[test]
+fn deterministic_parallel_search_orders_by_path() {
+    let root = fixture_root();
+    let paths: Vec<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .threads(4)
+        .deterministic()
+        .build()
+        .unwrap()
+        .map(|m| m.path)
+        .collect();
+
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(paths, sorted);
+    assert_eq!(paths.len(), 5);
+
+    let files = SearchBuilder::new("irrelevant")
+        .path(&root)
+        .threads(4)
+        .deterministic()
+        .walk_files()
+        .unwrap();
+    let mut sorted = files.clone();
+    sorted.sort();
+    assert_eq!(files, sorted);
+}
+
+#[test]
+fn limit_works_with_parallel_search() {
+    let root = fixture_root();
+    let mut count = 0usize;
+    SearchBuilder::new("alpha")
+        .path(&root)
+        .threads(4)
+        .limit(2)
+        .for_each(|_mat| {
+            count += 1;
+            true
+        })
+        .unwrap();
+
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn multiple_patterns_record_pattern_index() {
+    let results = SearchBuilder::new("alpha")
+        .pattern("charlie")
+        .search_slice(b"alpha\nbravo\ncharlie\n")
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].submatches[0].pattern, 0);
+    assert_eq!(results[1].submatches[0].pattern, 1);
+}
+
+#[test]
+fn pattern_index_follows_case_and_word_rules() {
+    // An uppercase literal turns smart case off for every pattern.
+    let cased = SearchBuilder::new("Foo")
+        .pattern("foo")
+        .search_slice(b"foo\nFoo\n")
+        .unwrap();
+    let patterns: Vec<_> = cased.iter().map(|m| m.submatches[0].pattern).collect();
+    assert_eq!(patterns, vec![1, 0]);
+
+    // With `word`, the earlier pattern matches at the same start but not
+    // as a whole word.
+    let words = SearchBuilder::new("foo")
+        .pattern("foobar")
+        .word()
+        .search_slice(b"foobar baz\n")
+        .unwrap();
+    assert_eq!(words[0].submatches[0].pattern, 1);
+
+    let many: Vec<String> = (0..500).map(|i| format!("tok{i}x")).collect();
+    let mut search = SearchBuilder::new(many[0].clone());
+    for pattern in &many[1..] {
+        search = search.pattern(pattern.clone());
+    }
+    let results = search.search_slice(b"a tok7x\nb tok499x tok42x\n").unwrap();
+    let patterns: Vec<_> = results
+        .iter()
+        .flat_map(|m| m.submatches.iter().map(|s| s.pattern))
+        .collect();
+    assert_eq!(patterns, vec![7, 499, 42]);
+}
+
+#[test]
+fn patterns_can_be_read_from_file() {
+    let file = std::env::temp_dir().join(format!("ripgrep-api-patterns-{}", std::process::id()));
+    std::fs::write(&file, "bravo\r\ncharlie\n").unwrap();
+
+    let results = SearchBuilder::new("alpha")
+        .patterns_from_file(&file)
+        .search_slice(b"alpha\nbravo\ncharlie\ndelta\n")
+        .unwrap();
+    std::fs::remove_file(&file).unwrap();
+
+    let patterns: Vec<_> = results.iter().map(|m| m.submatches[0].pattern).collect();
+    assert_eq!(patterns, vec![0, 1, 2]);
+
+    let missing = SearchBuilder::new("alpha")
+        .patterns_from_file(fixture_root().join("missing-patterns.txt"))
+        .search_slice(b"alpha\n");
+    assert!(matches!(missing, Err(ripgrep_api::SearchError::Io(_))));
+}
+
+#[test]
+fn invert_match_yields_non_matching_lines() {
+    let haystack = b"alpha\nbravo\nalpha\ncharlie\n";
+    let results = SearchBuilder::new("alpha")
+        .invert_match()
+        .search_slice(haystack)
+        .unwrap();
+
+    let lines: Vec<_> = results.iter().map(|m| m.line_text.trim_end()).collect();
+    assert_eq!(lines, vec!["bravo", "charlie"]);
+    assert!(results.iter().all(|m| m.submatches.is_empty()));
+
+    let mut streamed = 0usize;
+    SearchBuilder::new("alpha")
+        .invert_match()
+        .path(fixture_root().join("root.txt"))
+        .for_each(|_mat| {
+            streamed += 1;
+            true
+        })
+        .unwrap();
+    assert_eq!(streamed, 2);
+}
+
+#[test]
+fn invert_match_applies_to_count_and_files() {
+    let root = fixture_root();
+    let total = SearchBuilder::new("alpha")
+        .path(root.join("root.txt"))
+        .invert_match()
+        .count()
+        .unwrap();
+    assert_eq!(total, 2);
+
+    let files = SearchBuilder::new("alpha")
+        .path(root.join("nested"))
+        .invert_match()
+        .files_with_matches()
+        .unwrap();
+    let rel_files: BTreeSet<_> = files.iter().map(|path| rel(path, &root)).collect();
+    assert_eq!(
+        rel_files,
+        BTreeSet::from([PathBuf::from("nested/inner.rs")])
+    );
+}
+
+#[test]
+fn multiline_matches_span_lines() {
+    let haystack = b"alpha\nbravo\ncharlie\n";
+    let results = SearchBuilder::new(r"alpha\nbravo")
+        .multiline()
+        .search_slice(haystack)
+        .unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].line, Some(1));
+    assert_eq!(results[0].end_line, Some(2));
+    assert_eq!(results[0].submatches[0].start, 0);
+    assert_eq!(results[0].submatches[0].end, 11);
+
+    let without_dotall = SearchBuilder::new("bravo.charlie")
+        .multiline()
+        .search_slice(haystack)
+        .unwrap();
+    assert!(without_dotall.is_empty());
+
+    let dotall = SearchBuilder::new("bravo.charlie")
+        .multiline_dotall()
+        .search_slice(haystack)
+        .unwrap();
+    assert_eq!(dotall.len(), 1);
+    assert_eq!(dotall[0].line, Some(2));
+    assert_eq!(dotall[0].end_line, Some(3));
+}
+
+#[test]
+fn capture_groups_are_exposed() {
+    let results = SearchBuilder::new(r"(?P<key>\w+)=(\d+)?")
+        .search_slice(b"answer=42 empty=\n")
+        .unwrap();
+
+    let submatches = &results[0].submatches;
+    assert_eq!(submatches.len(), 2);
+
+    let first = &submatches[0];
+    assert_eq!(first.group(0).unwrap().text, "answer=42");
+    let key = first.name("key").unwrap();
+    assert_eq!((key.index, key.start, key.end), (1, 0, 6));
+    assert_eq!(first.group(2).unwrap().text, "42");
+
+    let second = &submatches[1];
+    assert_eq!(second.name("key").unwrap().text, "empty");
+    assert!(second.group(2).is_none());
+
+    let plain = SearchBuilder::new("answer")
+        .search_slice(b"answer=42\n")
+        .unwrap();
+    assert!(plain[0].submatches[0].captures.is_empty());
+}
+
+#[cfg(feature = "pcre2")]
+#[test]
+fn capture_groups_work_with_pcre2() {
+    let results = SearchBuilder::new(r"(?<word>foo)(bar)\1")
+        .pcre2()
+        .search_slice(b"xfoobarfoo\n")
+        .unwrap();
+
+    let submatch = &results[0].submatches[0];
+    assert_eq!(submatch.name("word").unwrap().text, "foo");
+    assert_eq!(submatch.group(2).unwrap().start, 4);
+}
+
+#[test]
+fn replace_fills_in_capture_references() {
+    let results = SearchBuilder::new(r"(?P<key>\w+)=(\d+)")
+        .replace("${key}: $2 ($$)")
+        .search_slice(b"a=1 b=2\n")
+        .unwrap();
+
+    assert_eq!(results[0].line_text, "a=1 b=2\n");
+    assert_eq!(
+        results[0].replacement.as_deref(),
+        Some("a: 1 ($) b: 2 ($)\n")
+    );
+
+    let plain = SearchBuilder::new("a").search_slice(b"abc\n").unwrap();
+    assert!(plain[0].replacement.is_none());
+}
+
+#[test]
+fn replace_with_uses_closure_per_submatch() {
+    let results = SearchBuilder::new(r"\d+")
+        .replace_with(|submatch| format!("<{}>", submatch.end - submatch.start))
+        .search_slice(b"x 12 y 345\n")
+        .unwrap();
+
+    assert_eq!(results[0].replacement.as_deref(), Some("x <2> y <3>\n"));
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ripgrep-api-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn rewrite_dry_run_reports_diffs_without_writing() {
+    let dir = scratch_dir("rewrite-dry-run");
+    let file = dir.join("a.txt");
+    std::fs::write(
+        &file,
+        "one\ntwo foo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten foo",
+    )
+    .unwrap();
+
+    let report = SearchBuilder::new("foo")
+        .path(&dir)
+        .replace("bar")
+        .dry_run()
+        .rewrite()
+        .unwrap();
+
+    assert!(report.dry_run);
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].replacements, 2);
+    let diff = report.files[0].diff.as_deref().unwrap();
+    assert!(diff.contains("@@ -1,5 +1,5 @@\n one\n-two foo\n+two bar\n three\n four\n five\n@@"));
+    assert!(diff.ends_with(
+        "@@ -7,4 +7,4 @@\n seven\n eight\n nine\n-ten foo\n\\ No newline at end of file\n+ten bar\n\\ No newline at end of file\n"
+    ));
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "one\ntwo foo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten foo"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rewrite_applies_replacements_with_backups() {
+    let dir = scratch_dir("rewrite-apply");
+    std::fs::write(dir.join("a.txt"), "foo = 1\nfoo = 2\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "nothing here\n").unwrap();
+
+    let report = SearchBuilder::new(r"(\w+) = (\d)")
+        .path(&dir)
+        .replace("$2 = $1")
+        .backup(".bak")
+        .rewrite()
+        .unwrap();
+
+    assert!(!report.dry_run);
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].replacements, 2);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("a.txt")).unwrap(),
+        "1 = foo\n2 = foo\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("a.txt.bak")).unwrap(),
+        "foo = 1\nfoo = 2\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("b.txt")).unwrap(),
+        "nothing here\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_rewrite_restores_earlier_files() {
+    let dir = scratch_dir("rewrite-rollback");
+    std::fs::write(dir.join("a.txt"), "foo\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "foo\n").unwrap();
+    // The backup of the second file cannot be written over a directory.
+    std::fs::create_dir(dir.join("b.txt.bak")).unwrap();
+
+    let result = SearchBuilder::new("foo")
+        .path(&dir)
+        .replace("bar")
+        .backup(".bak")
+        .rewrite();
+
+    assert!(matches!(result, Err(ripgrep_api::SearchError::Io(_))));
+    assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "foo\n");
+    assert_eq!(std::fs::read_to_string(dir.join("b.txt")).unwrap(), "foo\n");
+    let mut left: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    left.sort();
+    assert_eq!(left, vec!["a.txt", "b.txt", "b.txt.bak"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unreadable_file_fails_rewrite_before_writing() {
+    let dir = scratch_dir("rewrite-unreadable");
+    std::fs::write(dir.join("a.txt"), "foo\n").unwrap();
+    let result = SearchBuilder::new("foo")
+        .paths([dir.clone(), dir.join("missing.txt")])
+        .replace("bar")
+        .rewrite();
+
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "foo\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn rewrite_keeps_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("rewrite-permissions");
+    let file = dir.join("run.sh");
+    std::fs::write(&file, "echo foo\n").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o751)).unwrap();
+
+    SearchBuilder::new("foo")
+        .path(&dir)
+        .replace("bar")
+        .rewrite()
+        .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "echo bar\n");
+    let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o751);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rewrite_requires_a_replacement() {
+    let result = SearchBuilder::new("foo").path(fixture_root()).rewrite();
+    assert!(matches!(
+        result,
+        Err(ripgrep_api::SearchError::InvalidReplacement(_))
+    ));
+}
+
+#[test]
+fn per_file_errors_are_reported_without_aborting() {
+    let root = fixture_root();
+    let missing = root.join("does-not-exist");
+    let report = SearchReport::new();
+
+    let results: Vec<_> = SearchBuilder::new("alpha")
+        .paths([root.join("root.txt"), missing.clone()])
+        .report(&report)
+        .build()
+        .unwrap()
+        .collect();
+    assert_eq!(results.len(), 2);
+
+    let errors = report.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path.as_deref(), Some(missing.as_path()));
+
+    let total = SearchBuilder::new("alpha")
+        .paths([missing.clone(), root.join("root.txt")])
+        .threads(2)
+        .report(&report)
+        .count()
+        .unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(report.error_count(), 1);
+
+    let strict = SearchBuilder::new("alpha")
+        .paths([missing, root.join("root.txt")])
+        .strict()
+        .count();
+    assert!(matches!(strict, Err(ripgrep_api::SearchError::Walk(_))));
+}
+
+#[test]
+fn failing_file_keeps_earlier_matches_with_any_thread_count() {
+    struct Failing;
+
+    impl std::io::Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("device gone"))
+        }
+    }
+
+    // One matching line, then a read error.
+    fn open(_: &Path) -> std::io::Result<Box<dyn std::io::Read>> {
+        use std::io::Read;
+        Ok(Box::new(std::io::Cursor::new(b"alpha\n").chain(Failing)))
+    }
+
+    let dir = scratch_dir("failing-file");
+    std::fs::write(dir.join("a.txt"), "alpha\n").unwrap();
+
+    for threads in [1, 2] {
+        let report = SearchReport::new();
+        let results: Vec<_> = SearchBuilder::new("alpha")
+            .path(&dir)
+            .threads(threads)
+            .preprocessor_with(open)
+            .report(&report)
+            .build()
+            .unwrap()
+            .map(|mat| mat.line)
+            .collect();
+        assert_eq!(results, vec![Some(1)], "threads({threads})");
+        assert_eq!(report.error_count(), 1, "threads({threads})");
+
+        let strict = SearchBuilder::new("alpha")
+            .path(&dir)
+            .threads(threads)
+            .preprocessor_with(open)
+            .strict()
+            .count();
+        assert!(
+            matches!(strict, Err(ripgrep_api::SearchError::Io(_))),
+            "threads({threads})"
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sinks_receive_per_file_errors() {
+    #[derive(Default)]
+    struct Recorder {
+        matches: usize,
+        errors: Vec<String>,
+    }
+
+    impl MatchSink for Recorder {
+        fn matched(&mut self, _mat: &ripgrep_api::Match) -> bool {
+            self.matches += 1;
+            true
+        }
+
+        fn error(&mut self, error: &FileError) {
+            self.errors.push(error.to_string());
+        }
+    }
+
+    let root = fixture_root();
+    let mut recorder = Recorder::default();
+    SearchBuilder::new("alpha")
+        .paths([root.join("does-not-exist"), root.join("root.txt")])
+        .search_with(&mut recorder)
+        .unwrap();
+
+    assert_eq!(recorder.matches, 2);
+    assert_eq!(recorder.errors.len(), 1);
+    assert!(recorder.errors[0].contains("does-not-exist"));
+}
+
+#[test]
+fn report_collects_search_statistics() {
+    let root = fixture_root();
+    let report = SearchReport::new();
+
+    let total = SearchBuilder::new("alpha")
+        .path(&root)
+        .report(&report)
+        .count()
+        .unwrap();
+    assert_eq!(total, 5);
+
+    let stats = report.stats();
+    assert_eq!(stats.files_walked, 6);
+    assert_eq!(stats.files_searched, 6);
+    assert_eq!(stats.files_with_matches, 4);
+    assert_eq!(stats.matched_lines, 5);
+    assert_eq!(stats.matches, 5);
+    assert_eq!(stats.skipped_binary, 0);
+    assert!(stats.bytes_searched > 0);
+}
+
+#[test]
+fn report_counts_files_skipped_by_size() {
+    let root = fixture_root();
+    let report = SearchReport::new();
+
+    let results: Vec<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .max_filesize(20)
+        .report(&report)
+        .build()
+        .unwrap()
+        .collect();
+    assert_eq!(results.len(), 2);
+
+    let stats = report.stats();
+    assert_eq!(stats.skipped_filesize, 3);
+    assert_eq!(stats.files_searched, 3);
+    assert_eq!(stats.files_walked, 6);
+}
+
+#[test]
+fn only_matching_yields_each_submatch() {
+    let haystack = b"x = 1; y = 22\nnone\nz = 333\n";
+    let matches = SearchBuilder::new(r"(?P<name>\w) = (\d+)")
+        .only_matching()
+        .replace("$2")
+        .search_slice(haystack)
+        .unwrap();
+
+    let found: Vec<_> = matches
+        .iter()
+        .map(|m| (m.line, m.column, m.line_text.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Some(1), Some(1), "x = 1"),
+            (Some(1), Some(8), "y = 22"),
+            (Some(3), Some(1), "z = 333"),
+        ]
+    );
+
+    let second = &matches[1];
+    assert_eq!(second.submatches.len(), 1);
+    assert_eq!(second.submatches[0].end, second.bytes.len());
+    let name = second.submatches[0].name("name").unwrap();
+    assert_eq!((name.start, name.end, name.text.as_str()), (0, 1, "y"));
+    assert_eq!(second.replacement.as_deref(), Some("22"));
+}
+
+#[test]
+fn matches_and_context_carry_absolute_byte_offsets() {
+    let haystack = b"zero\none match\ntwo\n";
+    let matches = SearchBuilder::new("match")
+        .before_context(1)
+        .search_slice(haystack)
+        .unwrap();
+
+    assert_eq!(matches.len(), 1);
+    let mat = &matches[0];
+    assert_eq!(mat.byte_offset, 5);
+    assert_eq!(mat.submatches[0].byte_offset, 9);
+    assert_eq!(mat.context.len(), 1);
+    assert_eq!(mat.context[0].byte_offset, 0);
+
+    let pieces = SearchBuilder::new("o")
+        .only_matching()
+        .search_slice(haystack)
+        .unwrap();
+    let offsets: Vec<_> = pieces.iter().map(|m| m.byte_offset).collect();
+    assert_eq!(offsets, vec![3, 5, 17]);
+}
+
+#[derive(Default)]
+struct GroupRecorder {
+    events: Vec<String>,
+}
+
+impl MatchSink for GroupRecorder {
+    fn matched(&mut self, mat: &ripgrep_api::Match) -> bool {
+        let context: Vec<_> = mat.context.iter().map(|line| line.line.unwrap()).collect();
+        self.events
+            .push(format!("match {} {:?}", mat.line.unwrap(), context));
+        true
+    }
+
+    fn context(&mut self, line: &ripgrep_api::ContextLine) -> bool {
+        self.events.push(format!("context {}", line.line.unwrap()));
+        true
+    }
+
+    fn context_break(&mut self) -> bool {
+        self.events.push("--".to_string());
+        true
+    }
+}
+
+#[test]
+fn streaming_sinks_can_receive_attached_context_and_breaks() {
+    let haystack = b"a\nhit\nb\nc\nd\ne\nhit\nf\n";
+
+    let mut separate = GroupRecorder::default();
+    SearchBuilder::new("hit")
+        .context(1)
+        .search_slice_with(haystack, &mut separate)
+        .unwrap();
+    assert_eq!(
+        separate.events,
+        vec![
+            "context 1",
+            "match 2 []",
+            "context 3",
+            "--",
+            "context 6",
+            "match 7 []",
+            "context 8",
+        ]
+    );
+
+    let mut attached = GroupRecorder::default();
+    SearchBuilder::new("hit")
+        .context(1)
+        .attach_context()
+        .search_slice_with(haystack, &mut attached)
+        .unwrap();
+    assert_eq!(
+        attached.events,
+        vec!["match 2 [1, 3]", "--", "match 7 [6, 8]"]
+    );
+}
+
+#[test]
+fn nearby_matches_report_shared_context_once() {
+    let haystack = b"a\nhit\nb\nhit\nc\n";
+    let matches = SearchBuilder::new("hit")
+        .context(1)
+        .search_slice(haystack)
+        .unwrap();
+
+    let context: Vec<Vec<_>> = matches
+        .iter()
+        .map(|mat| {
+            mat.context
+                .iter()
+                .map(|line| (line.line.unwrap(), line.kind))
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        context,
+        vec![
+            vec![(1, ContextKind::Before), (3, ContextKind::After)],
+            vec![(5, ContextKind::After)],
+        ]
+    );
+}
+
+#[test]
+fn file_results_merge_context_into_hunks() {
+    let dir = scratch_dir("file-results");
+    std::fs::write(dir.join("a.txt"), "a\nhit\nb\nhit\nc\nd\ne\nf\nhit\ng\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "nothing here\n").unwrap();
+
+    let results = SearchBuilder::new("hit")
+        .path(&dir)
+        .context(1)
+        .file_results()
+        .unwrap();
+
+    assert_eq!(results.len(), 1);
+    let file = &results[0];
+    assert_eq!(file.path, dir.join("a.txt"));
+    assert_eq!(file.matches().count(), 3);
+
+    let hunks: Vec<_> = file
+        .hunks
+        .iter()
+        .map(|hunk| {
+            let lines: Vec<_> = hunk
+                .lines
+                .iter()
+                .map(|line| match line {
+                    HunkLine::Match(mat) => {
+                        format!("{}:{}", mat.line.unwrap(), mat.line_text.trim_end())
+                    }
+                    HunkLine::Context(line) => {
+                        format!("{}-{}", line.line.unwrap(), line.line_text.trim_end())
+                    }
+                })
+                .collect();
+            (hunk.start_line, hunk.end_line, lines)
+        })
+        .collect();
+    assert_eq!(
+        hunks,
+        vec![
+            (
+                Some(1),
+                Some(5),
+                vec!["1-a", "2:hit", "3-b", "4:hit", "5-c"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            ),
+            (
+                Some(8),
+                Some(10),
+                vec!["8-f", "9:hit", "10-g"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            ),
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn file_results_split_hunks_without_context() {
+    let dir = scratch_dir("file-results-no-context");
+    std::fs::write(dir.join("a.txt"), "hit\na\nb\nc\nhit\nhit\n").unwrap();
+
+    let results = SearchBuilder::new("hit").path(&dir).file_results().unwrap();
+
+    assert_eq!(results.len(), 1);
+    let hunks: Vec<_> = results[0]
+        .hunks
+        .iter()
+        .map(|hunk| (hunk.start_line, hunk.end_line, hunk.lines.len()))
+        .collect();
+    assert_eq!(hunks, vec![(Some(1), Some(1), 1), (Some(5), Some(6), 2)]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sort_by_path_orders_walks_and_results() {
+    let root = fixture_root();
+    let files: Vec<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .sort_by(SortKey::Path, Order::Descending)
+        .walk_files()
+        .unwrap()
+        .iter()
+        .map(|path| rel(path, &root))
+        .collect();
+
+    let mut expected = files.clone();
+    expected.sort();
+    expected.reverse();
+    assert_eq!(files, expected);
+
+    let matched: Vec<_> = SearchBuilder::new("alpha")
+        .path(&root)
+        .threads(4)
+        .sort_by(SortKey::Path, Order::Ascending)
+        .build()
+        .unwrap()
+        .map(|mat| rel(&mat.path, &root))
+        .collect();
+    let mut expected = matched.clone();
+    expected.sort();
+    assert_eq!(matched, expected);
+}
+
+#[test]
+fn sort_by_modified_time() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = scratch_dir("sort-modified");
+    let now = SystemTime::now();
+    for (name, age) in [("a.txt", 30), ("b.txt", 10), ("c.txt", 20)] {
+        let path = dir.join(name);
+        std::fs::write(&path, "needle\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(now - Duration::from_secs(age)).unwrap();
+    }
+
+    let newest_first: Vec<_> = SearchBuilder::new("needle")
+        .path(&dir)
+        .sort_by(SortKey::Modified, Order::Descending)
+        .files_with_matches()
+        .unwrap()
+        .iter()
+        .map(|path| rel(path, &dir))
+        .collect();
+    assert_eq!(
+        newest_first,
+        vec![
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt"),
+            PathBuf::from("a.txt"),
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn binary_modes_skip_convert_or_search_as_text() {
+    #[derive(Default)]
+    struct Recorder {
+        matches: usize,
+        binary: Vec<u64>,
+    }
+
+    impl MatchSink for Recorder {
+        fn matched(&mut self, _mat: &ripgrep_api::Match) -> bool {
+            self.matches += 1;
+            true
+        }
+
+        fn binary(&mut self, _path: &Path, offset: u64) -> bool {
+            self.binary.push(offset);
+            true
+        }
+    }
+
+    let dir = scratch_dir("binary-modes");
+    let file = dir.join("data.bin");
+    std::fs::write(&file, b"text match\n\0binary\nmatch again\n").unwrap();
+
+    let search = |mode| {
+        let report = SearchReport::new();
+        let mut recorder = Recorder::default();
+        SearchBuilder::new("match")
+            .path(&dir)
+            .binary(mode)
+            .report(&report)
+            .search_with(&mut recorder)
+            .unwrap();
+        (recorder, report)
+    };
+
+    let (skipped, report) = search(BinaryMode::Skip);
+    assert_eq!(skipped.matches, 0);
+    assert_eq!(skipped.binary, vec![11]);
+    assert_eq!(report.stats().skipped_binary, 1);
+    assert_eq!(report.binary_files(), vec![(file.clone(), 11)]);
+
+    let (converted, report) = search(BinaryMode::Convert);
+    assert_eq!(converted.matches, 2);
+    assert_eq!(converted.binary, vec![11]);
+    let stats = report.stats();
+    assert_eq!((stats.binary_files, stats.skipped_binary), (1, 0));
+
+    let (text, report) = search(BinaryMode::Text);
+    assert_eq!(text.matches, 2);
+    assert!(text.binary.is_empty());
+    assert_eq!(report.stats().binary_files, 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn encodings_are_transcoded_before_matching() {
+    let utf16: Vec<u8> = std::iter::once(0xFEFF_u16)
+        .chain("caf\u{e9} ol\u{e9}\n".encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let matches = SearchBuilder::new("olé").search_slice(&utf16).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].line_text, "café olé\n");
+
+    let latin1 = b"na\xefve caf\xe9\n";
+    let matches = SearchBuilder::new("café")
+        .encoding("windows-1252")
+        .search_slice(latin1)
+        .unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].line_text, "naïve café\n");
+
+    let raw = SearchBuilder::new("olé")
+        .encoding("none")
+        .search_slice(&utf16)
+        .unwrap();
+    assert!(raw.is_empty());
+
+    let err = SearchBuilder::new("x")
+        .encoding("klingon")
+        .search_slice(b"x\n")
+        .unwrap_err();
+    assert!(matches!(err, ripgrep_api::SearchError::InvalidEncoding(_)));
+    assert!(matches!(
+        SearchBuilder::new("x").encoding("klingon").build(),
+        Err(ripgrep_api::SearchError::InvalidEncoding(_))
+    ));
+    assert!(matches!(
+        SearchBuilder::new("x").encoding("klingon").compile(),
+        Err(ripgrep_api::SearchError::InvalidEncoding(_))
+    ));
+}
+
+#[test]
+fn search_zip_decompresses_by_extension() {
+    let dir = scratch_dir("search-zip");
+    let plain = dir.join("app.log");
+    std::fs::write(&plain, "boot\nneedle in the log\n").unwrap();
+    let Ok(status) = std::process::Command::new("gzip").arg(&plain).status() else {
+        // No gzip on this machine, so nothing can be decompressed.
+        return;
+    };
+    assert!(status.success());
+    let compressed = dir.join("app.log.gz");
+
+    let raw = SearchBuilder::new("needle").path(&dir).count().unwrap();
+    assert_eq!(raw, 0);
+
+    let matches: Vec<_> = SearchBuilder::new("needle")
+        .path(&dir)
+        .search_zip()
+        .build()
+        .unwrap()
+        .collect();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].path, compressed);
+    assert_eq!(matches[0].line, Some(2));
+    assert_eq!(matches[0].line_text, "needle in the log\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn preprocessor_closure_respects_pre_glob() {
+    let dir = scratch_dir("preprocessor-with");
+    std::fs::write(dir.join("a.txt"), "shout\n").unwrap();
+    std::fs::write(dir.join("b.md"), "shout\n").unwrap();
+
+    let matches: Vec<_> = SearchBuilder::new("SHOUT")
+        .path(&dir)
+        .preprocessor_with(|path| {
+            let text = std::fs::read_to_string(path)?;
+            Ok(Box::new(std::io::Cursor::new(text.to_uppercase())))
+        })
+        .pre_glob("*.txt")
+        .build()
+        .unwrap()
+        .collect();
+
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].path, dir.join("a.txt"));
+
+    let invalid = SearchBuilder::new("SHOUT")
+        .path(&dir)
+        .preprocessor_with(|path| Ok(Box::new(std::fs::File::open(path)?)))
+        .pre_glob("[")
+        .build();
+    assert!(matches!(
+        invalid,
+        Err(ripgrep_api::SearchError::InvalidGlob(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn preprocessor_command_output_is_searched() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("preprocessor-command");
+    let script = dir.join("upper.sh");
+    std::fs::write(&script, "#!/bin/sh\ntr a-z A-Z\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let docs = dir.join("docs");
+    std::fs::create_dir(&docs).unwrap();
+    std::fs::write(docs.join("note.txt"), "quiet\nwhisper\n").unwrap();
+
+    let matches = SearchBuilder::new("WHISPER")
+        .path(&docs)
+        .preprocessor(&script)
+        .build()
+        .unwrap()
+        .collect::<Vec<_>>();
+
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].path, docs.join("note.txt"));
+    assert_eq!(matches[0].line, Some(2));
+    assert_eq!(matches[0].line_text, "WHISPER\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "archives")]
+#[test]
+fn archive_members_are_searched_under_virtual_paths() {
+    use std::io::Write;
+
+    let dir = scratch_dir("archives");
+    let zip_path = dir.join("bundle.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("src/main.rs", options).unwrap();
+    zip.write_all(b"fn main() {}\n// needle\n").unwrap();
+    zip.start_file("README.md", options).unwrap();
+    zip.write_all(b"needle docs\n").unwrap();
+    zip.finish().unwrap();
+
+    let tar_path = dir.join("vendor.tar");
+    let mut tar = tar::Builder::new(std::fs::File::create(&tar_path).unwrap());
+    let contents = b"// needle in util\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, "lib/util.rs", &contents[..])
+        .unwrap();
+    tar.finish().unwrap();
+    drop(tar);
+
+    let opaque = SearchBuilder::new("needle").path(&dir).count().unwrap();
+    assert_eq!(opaque, 0);
+
+    let matches: BTreeSet<_> = SearchBuilder::new("needle")
+        .path(&dir)
+        .search_archives()
+        .glob("*.rs")
+        .build()
+        .unwrap()
+        .map(|mat| (rel(&mat.path, &dir), mat.line))
+        .collect();
+    assert_eq!(
+        matches,
+        BTreeSet::from([
+            (PathBuf::from("bundle.zip!/src/main.rs"), Some(2)),
+            (PathBuf::from("vendor.tar!/lib/util.rs"), Some(1)),
+        ])
+    );
+
+    let files: BTreeSet<_> = SearchBuilder::new("needle")
+        .path(&dir)
+        .search_archives()
+        .type_("markdown")
+        .walk_files()
+        .unwrap()
+        .iter()
+        .map(|path| rel(path, &dir))
+        .collect();
+    assert_eq!(
+        files,
+        BTreeSet::from([PathBuf::from("bundle.zip!/README.md")])
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "archives")]
+#[test]
+fn unreadable_archive_members_are_skipped_and_reported() {
+    use std::io::Write;
+
+    let dir = scratch_dir("archive-bad-member");
+    let zip_path = dir.join("bundle.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("a.txt", options).unwrap();
+    zip.write_all(b"needle a\n").unwrap();
+    zip.start_file("b.txt", options).unwrap();
+    zip.write_all(b"needle b\n").unwrap();
+    zip.finish().unwrap();
+
+    // Mark the first member as bzip2, which the zip reader does not support.
+    let mut bytes = std::fs::read(&zip_path).unwrap();
+    bytes[8..10].copy_from_slice(&12_u16.to_le_bytes());
+    let central = bytes
+        .windows(4)
+        .position(|window| window == b"PK\x01\x02")
+        .unwrap();
+    bytes[central + 10..central + 12].copy_from_slice(&12_u16.to_le_bytes());
+    std::fs::write(&zip_path, bytes).unwrap();
+
+    let report = SearchReport::new();
+    let matches: Vec<_> = SearchBuilder::new("needle")
+        .path(&dir)
+        .search_archives()
+        .threads(2)
+        .report(&report)
+        .build()
+        .unwrap()
+        .map(|mat| rel(&mat.path, &dir))
+        .collect();
+    assert_eq!(matches, vec![PathBuf::from("bundle.zip!/b.txt")]);
+
+    let errors = report.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        rel(errors[0].path.as_ref().unwrap(), &dir),
+        PathBuf::from("bundle.zip!/a.txt")
+    );
+
+    let count = SearchBuilder::new("needle")
+        .path(&dir)
+        .search_archives()
+        .count()
+        .unwrap();
+    assert_eq!(count, 1);
+
+    let strict = SearchBuilder::new("needle")
+        .path(&dir)
+        .search_archives()
+        .strict()
+        .count();
+    assert!(strict.is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compiled_search_runs_repeatedly_and_across_threads() {
+    fn assert_shareable<T: Clone + Send + Sync>(_: &T) {}
+
+    let root = fixture_root();
+    let search = SearchBuilder::new("alpha").path(&root).compile().unwrap();
+    assert_shareable(&search);
+
+    let expected = SearchBuilder::new("alpha").path(&root).count().unwrap();
+    assert_eq!(search.count().unwrap(), expected);
+    assert_eq!(search.search().unwrap().count() as u64, expected);
+
+    let counts: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| search.count().unwrap()))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert_eq!(counts, vec![expected; 4]);
+
+    let nested = search
+        .with_paths([root.join("nested")])
+        .files_with_matches();
+    let nested: Vec<_> = nested.unwrap().iter().map(|p| rel(p, &root)).collect();
+    assert_eq!(
+        nested,
+        vec![
+            PathBuf::from("nested/deeper/deep.txt"),
+            PathBuf::from("nested/inner.rs")
+        ]
+    );
+
+    let in_memory = search.search_slice(b"one\nalpha two\n").unwrap();
+    assert_eq!(in_memory.len(), 1);
+    assert_eq!(in_memory[0].line, Some(2));
+}
+
+#[test]
+fn compiled_search_with_pattern_keeps_other_options() {
+    let root = fixture_root();
+    let search = SearchBuilder::new("alpha")
+        .path(&root)
+        .glob("*.rs")
+        .compile()
+        .unwrap();
+    let swapped = search.with_pattern("ALPHA").unwrap();
+
+    let files: Vec<_> = swapped
+        .files_with_matches()
+        .unwrap()
+        .iter()
+        .map(|p| rel(p, &root))
+        .collect();
+    assert!(files.iter().all(|p| p.extension() == Some("rs".as_ref())));
+    assert_eq!(
+        swapped.count().unwrap(),
+        SearchBuilder::new("ALPHA")
+            .path(&root)
+            .glob("*.rs")
+            .count()
+            .unwrap()
+    );
+    assert!(matches!(
+        search.with_pattern("("),
+        Err(ripgrep_api::SearchError::InvalidPattern(_))
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn search_stream_yields_matches_and_stops_on_drop() {
+    use futures_core::Stream;
+
+    let root = fixture_root();
+    let expected = SearchBuilder::new("alpha").path(&root).count().unwrap();
+
+    let mut stream = Box::pin(
+        SearchBuilder::new("alpha")
+            .path(&root)
+            .search_stream()
+            .unwrap(),
+    );
+    let mut found = 0;
+    while let Some(result) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+        assert!(result.unwrap().path.starts_with(&root));
+        found += 1;
+    }
+    assert_eq!(found, expected);
+
+    let mut stream = Box::pin(
+        SearchBuilder::new("alpha")
+            .path(&root)
+            .search_stream()
+            .unwrap(),
+    );
+    let first = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await;
+    assert!(matches!(first, Some(Ok(_))));
+    drop(stream);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn search_stream_requires_a_runtime() {
+    assert!(matches!(
+        SearchBuilder::new("alpha").search_stream(),
+        Err(ripgrep_api::SearchError::Io(err)) if err.kind() == std::io::ErrorKind::Unsupported
+    ));
+}
+
+#[test]
+fn cancelled_search_keeps_partial_results() {
+    let root = fixture_root();
+    for invert in [false, true] {
+        let token = CancellationToken::new();
+        let mut builder = SearchBuilder::new("alpha").path(&root).cancel_token(&token);
+        if invert {
+            builder = builder.invert_match();
+        }
+        let mut found = 0;
+        let result = builder.for_each(|_| {
+            found += 1;
+            token.cancel();
+            true
+        });
+        assert!(matches!(
+            result,
+            Err(ripgrep_api::SearchError::Cancelled { partial: None })
+        ));
+        assert_eq!(found, 1);
+    }
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let count = SearchBuilder::new("alpha")
+        .path(&root)
+        .threads(4)
+        .cancel_token(&token)
+        .count();
+    assert!(matches!(
+        count,
+        Err(ripgrep_api::SearchError::Cancelled {
+            partial: Some(Partial::Count(0))
+        })
+    ));
+
+    let files = SearchBuilder::new("alpha")
+        .path(&root)
+        .cancel_token(&token)
+        .walk_files();
+    assert!(matches!(
+        files,
+        Err(ripgrep_api::SearchError::Cancelled {
+            partial: Some(Partial::Files(files))
+        }) if files.is_empty()
+    ));
+}
+
+#[test]
+fn cancelled_collectors_return_what_they_collected() {
+    let dir = scratch_dir("cancel-partial");
+    std::fs::write(dir.join("a.txt"), "alpha\nalpha\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "alpha\n").unwrap();
+    std::fs::write(dir.join("c.txt"), "alpha\n").unwrap();
+
+    // Cancel the search as the second file is opened.
+    let cancelling = |token: &CancellationToken| {
+        let token = token.clone();
+        let opened = std::sync::atomic::AtomicUsize::new(0);
+        move |path: &Path| -> std::io::Result<Box<dyn std::io::Read>> {
+            if opened.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 1 {
+                token.cancel();
+            }
+            Ok(Box::new(std::fs::File::open(path)?))
+        }
+    };
+
+    let token = CancellationToken::new();
+    let count = SearchBuilder::new("alpha")
+        .path(&dir)
+        .sort_by(SortKey::Path, Order::Ascending)
+        .cancel_token(&token)
+        .preprocessor_with(cancelling(&token))
+        .count();
+    assert!(matches!(
+        count,
+        Err(ripgrep_api::SearchError::Cancelled {
+            partial: Some(Partial::Count(2))
+        })
+    ));
+
+    let token = CancellationToken::new();
+    let files = SearchBuilder::new("alpha")
+        .path(&dir)
+        .sort_by(SortKey::Path, Order::Ascending)
+        .cancel_token(&token)
+        .preprocessor_with(cancelling(&token))
+        .files_with_matches();
+    match files {
+        Err(ripgrep_api::SearchError::Cancelled {
+            partial: Some(Partial::Files(files)),
+        }) => assert_eq!(files, vec![dir.join("a.txt")]),
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    let timed_out = SearchBuilder::new("alpha")
+        .path(&dir)
+        .deadline(std::time::Duration::ZERO)
+        .count_per_file();
+    assert!(matches!(
+        timed_out,
+        Err(ripgrep_api::SearchError::TimedOut {
+            partial: Some(Partial::Counts(counts))
+        }) if counts.is_empty()
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn deadline_reports_timeout() {
+    let root = fixture_root();
+    let mut search = SearchBuilder::new("alpha")
+        .path(&root)
+        .deadline(std::time::Duration::ZERO)
+        .build()
+        .unwrap();
+    assert_eq!(search.by_ref().count(), 0);
+    assert!(matches!(
+        search.take_error(),
+        Some(ripgrep_api::SearchError::TimedOut { partial: None })
+    ));
+
+    let compiled = SearchBuilder::new("alpha")
+        .path(&root)
+        .deadline(std::time::Duration::from_secs(60))
+        .compile()
+        .unwrap();
+    assert!(compiled.count().unwrap() > 0);
+    assert!(matches!(
+        SearchBuilder::new("alpha")
+            .deadline(std::time::Duration::ZERO)
+            .search_slice(b"alpha\n"),
+        Err(ripgrep_api::SearchError::TimedOut {
+            partial: Some(Partial::Matches(matches))
+        }) if matches.is_empty()
+    ));
+}